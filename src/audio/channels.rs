@@ -0,0 +1,207 @@
+use std::str::FromStr;
+
+use ffmpeg_next as ffmpeg;
+
+// largest channel count the remapper handles, enough for 7.1.4 and similar layouts
+const MAX_CHANNELS: usize = 16;
+
+/// Matrix used by the resampler when the source has more channels than the output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DownmixMode {
+    /// ITU-R BS.775 coefficients: center and surrounds at -3 dB
+    #[default]
+    Standard,
+    /// Dolby Pro Logic II matrix encoding, so a surround decoder can recover the rear channels
+    ProLogic2,
+    /// Center boosted and surrounds and LFE lowered, for intelligible dialog at low volume
+    Night,
+}
+
+impl DownmixMode {
+    /// libswresample options implementing this matrix.
+    pub fn resampler_options(self) -> ffmpeg::Dictionary<'static> {
+        let mut options = ffmpeg::Dictionary::new();
+        match self {
+            DownmixMode::Standard => {}
+            DownmixMode::ProLogic2 => options.set("matrix_encoding", "dplii"),
+            DownmixMode::Night => {
+                options.set("center_mix_level", "1.414");
+                options.set("surround_mix_level", "0.5");
+                options.set("lfe_mix_level", "0");
+            }
+        }
+        options
+    }
+}
+
+/// Number of output channels requested from the device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChannelCount {
+    /// The source channel count for surround content, otherwise the device default
+    #[default]
+    Auto,
+    Fixed(u16),
+}
+
+impl FromStr for ChannelCount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let count = match s {
+            "auto" => return Ok(ChannelCount::Auto),
+            "mono" => 1,
+            "stereo" => 2,
+            "5.1" => 6,
+            "7.1" => 8,
+            other => other.parse().map_err(|_| {
+                format!(
+                    "expected auto, mono, stereo, 5.1, 7.1 or a number, got '{}'",
+                    other
+                )
+            })?,
+        };
+        if count == 0 || count as usize > MAX_CHANNELS {
+            return Err(format!(
+                "channel count must be between 1 and {}",
+                MAX_CHANNELS
+            ));
+        }
+        Ok(ChannelCount::Fixed(count))
+    }
+}
+
+/// Rearrangement of output channels applied after resampling.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ChannelMap {
+    #[default]
+    Normal,
+    /// Exchange the first two channels
+    Swap,
+    /// Every channel plays the average of all channels
+    Mono,
+    /// Output channel `i` plays source channel `map[i]`; missing entries are silent
+    Custom(Vec<usize>),
+}
+
+impl ChannelMap {
+    /// Applies the map in place to interleaved samples.
+    pub fn apply(&self, samples: &mut [f32], channels: usize) {
+        if channels == 0 || channels > MAX_CHANNELS {
+            return;
+        }
+        match self {
+            ChannelMap::Normal => {}
+            ChannelMap::Swap if channels >= 2 => {
+                for frame in samples.chunks_exact_mut(channels) {
+                    frame.swap(0, 1);
+                }
+            }
+            ChannelMap::Swap => {}
+            ChannelMap::Mono => {
+                for frame in samples.chunks_exact_mut(channels) {
+                    let mean = frame.iter().sum::<f32>() / channels as f32;
+                    frame.fill(mean);
+                }
+            }
+            ChannelMap::Custom(map) => {
+                let mut source = [0.0_f32; MAX_CHANNELS];
+                for frame in samples.chunks_exact_mut(channels) {
+                    source[..channels].copy_from_slice(frame);
+                    for (i, sample) in frame.iter_mut().enumerate() {
+                        *sample = map
+                            .get(i)
+                            .and_then(|&from| source[..channels].get(from))
+                            .copied()
+                            .unwrap_or(0.0);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for ChannelMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(ChannelMap::Normal),
+            "swap" => Ok(ChannelMap::Swap),
+            "mono" => Ok(ChannelMap::Mono),
+            custom => custom
+                .split(',')
+                .map(|index| index.trim().parse::<usize>())
+                .collect::<Result<Vec<_>, _>>()
+                .map(ChannelMap::Custom)
+                .map_err(|_| {
+                    format!(
+                        "expected normal, swap, mono or a list like 0,1,4,5,2,3, got '{}'",
+                        custom
+                    )
+                }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_named_maps() {
+        assert_eq!("normal".parse(), Ok(ChannelMap::Normal));
+        assert_eq!("swap".parse(), Ok(ChannelMap::Swap));
+        assert_eq!("mono".parse(), Ok(ChannelMap::Mono));
+    }
+
+    #[test]
+    fn parses_custom_lists() {
+        assert_eq!("1,0".parse(), Ok(ChannelMap::Custom(vec![1, 0])));
+        assert_eq!(
+            "0, 1, 4, 5, 2, 3".parse(),
+            Ok(ChannelMap::Custom(vec![0, 1, 4, 5, 2, 3]))
+        );
+    }
+
+    #[test]
+    fn invalid_map_error_names_the_input() {
+        assert_eq!(
+            "0,-1".parse::<ChannelMap>(),
+            Err("expected normal, swap, mono or a list like 0,1,4,5,2,3, got '0,-1'".to_string())
+        );
+        // an empty entry is not read as channel 0
+        assert_eq!(
+            "0,,1".parse::<ChannelMap>().unwrap_err(),
+            "expected normal, swap, mono or a list like 0,1,4,5,2,3, got '0,,1'"
+        );
+    }
+
+    #[test]
+    fn channel_count_separates_unknown_names_from_bad_counts() {
+        assert_eq!("5.1".parse(), Ok(ChannelCount::Fixed(6)));
+        assert_eq!(
+            "quad".parse::<ChannelCount>(),
+            Err("expected auto, mono, stereo, 5.1, 7.1 or a number, got 'quad'".to_string())
+        );
+        let range = format!("channel count must be between 1 and {}", MAX_CHANNELS);
+        assert_eq!("0".parse::<ChannelCount>(), Err(range.clone()));
+        assert_eq!(
+            (MAX_CHANNELS + 1).to_string().parse::<ChannelCount>(),
+            Err(range)
+        );
+    }
+
+    #[test]
+    fn custom_map_silences_missing_channels() {
+        let mut samples = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6];
+        ChannelMap::Custom(vec![2, 0, 7]).apply(&mut samples, 3);
+        assert_eq!(samples, [0.3, 0.1, 0.0, 0.6, 0.4, 0.0]);
+    }
+
+    #[test]
+    fn swap_exchanges_the_first_two_channels() {
+        let mut samples = [0.1, 0.2, 0.3, 0.4];
+        ChannelMap::Swap.apply(&mut samples, 2);
+        assert_eq!(samples, [0.2, 0.1, 0.4, 0.3]);
+    }
+}
@@ -12,16 +12,15 @@ pub struct AudioDecoder {
 }
 
 impl AudioDecoder {
-    pub fn new(path: &str) -> Result<Self, ffmpeg::Error> {
+    /// Opens audio stream `stream_index` of `path`.
+    pub fn new(path: &str, stream_index: usize) -> Result<Self, ffmpeg::Error> {
         // Propagate errors opening the input so the caller can handle missing or inaccessible files
         let ictx = ffmpeg::format::input(path)?;
 
-        // Panic if no audio stream is found because this is unrecoverable at this layer
+        // Panic if the stream is gone because tracks are probed from the same file
         let stream = ictx
-            .streams()
-            .best(ffmpeg::media::Type::Audio)
-            .expect("no audio stream found in input (unsupported format)");
-        let stream_index = stream.index();
+            .stream(stream_index)
+            .expect("audio stream not found in input");
         let time_base = stream.time_base();
 
         // Build codec context; panic on invalid stream parameters since this indicates unrecoverable input
@@ -46,6 +45,23 @@ impl AudioDecoder {
     pub fn time_base(&self) -> ffmpeg::Rational {
         self.time_base
     }
+
+    /// Metadata value for `key`, matched case-insensitively, from the audio stream or else
+    /// the container.
+    pub fn tag(&self, key: &str) -> Option<String> {
+        let stream = self.ictx.stream(self.stream_index)?;
+        let value = stream.metadata().get(key).map(str::to_string);
+        value.or_else(|| self.ictx.metadata().get(key).map(str::to_string))
+    }
+
+    /// Seeks to the last keyframe at or before `seconds` and discards buffered decoder state.
+    pub fn seek(&mut self, seconds: f64) -> Result<(), ffmpeg::Error> {
+        let ts = (seconds / f64::from(ffmpeg::rescale::TIME_BASE)) as i64;
+        self.ictx.seek(ts, ..ts)?;
+        self.decoder.flush();
+        self.sent_eof = false;
+        Ok(())
+    }
 }
 
 impl Iterator for AudioDecoder {
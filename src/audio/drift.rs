@@ -0,0 +1,136 @@
+use std::collections::VecDeque;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+use std::time::{Duration, Instant};
+
+use crate::media::clock::{ClockMode, GlobalClock};
+
+// drift below this is left alone so the resampler is not constantly retuned
+const THRESHOLD: f64 = 0.002; // seconds
+// largest change of playback speed used for correction; 2% stays below what most ears notice
+const MAX_SPEED_CHANGE: f64 = 0.02;
+/// Lateness that compensation is still allowed to absorb before late audio is dropped.
+pub const MAX_COMPENSATED: f64 = 0.2; // seconds
+// a report older than this means the callback stopped playing, e.g. while paused
+const STALE_AFTER: Duration = Duration::from_millis(100);
+
+/// Media time being heard, published by the sink callback without taking locks.
+///
+/// The callback runs on a real-time thread, so it only stores atomics here; the playback
+/// thread and the decoders read them back to drive the clock and measure drift.
+pub struct DriftMonitor {
+    epoch: Instant,
+    // media seconds per second of output
+    speed: f64,
+    // f64 bits of the media time heard at `epoch`, extrapolated from the latest report;
+    // a single value so a reader never sees a position from one report and a time from another
+    origin: AtomicU64,
+    // nanoseconds after `epoch` of the latest report, u64::MAX until the first
+    reported: AtomicU64,
+    // seek serial of the audio reported, so a restarted feed never sees the old position
+    serial: AtomicU64,
+}
+
+impl DriftMonitor {
+    pub fn new(speed: f64) -> Self {
+        Self {
+            epoch: Instant::now(),
+            speed,
+            origin: AtomicU64::new(f64::NAN.to_bits()),
+            reported: AtomicU64::new(u64::MAX),
+            serial: AtomicU64::new(0),
+        }
+    }
+
+    /// Publishes that media time `heard`, decoded after seek `serial`, is reaching the speaker
+    /// right now.
+    pub fn report(&self, heard: f64, serial: u64) {
+        let elapsed = self.epoch.elapsed();
+        let origin = heard - elapsed.as_secs_f64() * self.speed;
+        self.origin.store(origin.to_bits(), Ordering::Relaxed);
+        self.reported
+            .store(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        // published last, so a reader that sees the new serial also sees the new position
+        self.serial.store(serial, Ordering::Release);
+    }
+
+    /// Media time being heard now, if the callback recently reported audio of seek `serial`.
+    pub fn heard(&self, serial: u64) -> Option<f64> {
+        if self.serial.load(Ordering::Acquire) != serial {
+            return None;
+        }
+        let elapsed = self.epoch.elapsed();
+        let reported = self.reported.load(Ordering::Relaxed);
+        if reported == u64::MAX
+            || elapsed.saturating_sub(Duration::from_nanos(reported)) > STALE_AFTER
+        {
+            return None;
+        }
+        let origin = f64::from_bits(self.origin.load(Ordering::Relaxed));
+        Some(origin + elapsed.as_secs_f64() * self.speed)
+    }
+
+    /// Moves the clock to the audio being heard, when audio is the master.
+    pub fn sync_clock(&self, clock: &GlobalClock, serial: u64) {
+        if let Some(heard) = self.heard(serial) {
+            clock.sync(ClockMode::Audio, heard);
+        }
+    }
+
+    /// Media time being heard and how far it is ahead of `clock`.
+    fn measurement(&self, clock: &GlobalClock, serial: u64) -> Option<(f64, f64)> {
+        if clock.is_paused() {
+            return None;
+        }
+        let heard = self.heard(serial)?;
+        Some((heard, heard - clock.now()))
+    }
+}
+
+/// Decoder-side controller turning drift measurements into resampler compensation.
+///
+/// Decoded audio is heard a while after it is corrected, so corrections still in flight are
+/// remembered and subtracted from the measurement instead of being applied again.
+pub struct DriftCompensator {
+    monitor: Arc<DriftMonitor>,
+    // seek serial of the audio this decoder produces
+    serial: u64,
+    // (end of the corrected chunk in media time, correction in seconds)
+    pending: VecDeque<(f64, f64)>,
+}
+
+impl DriftCompensator {
+    pub fn new(monitor: Arc<DriftMonitor>, serial: u64) -> Self {
+        Self {
+            monitor,
+            serial,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Seconds to stretch (positive) or shrink (negative) the chunk starting at `pts`.
+    pub fn correction(&mut self, pts: f64, duration: f64, clock: &GlobalClock) -> f64 {
+        let Some((heard, drift)) = self.monitor.measurement(clock, self.serial) else {
+            return 0.0;
+        };
+        while self.pending.front().is_some_and(|&(end, _)| end <= heard) {
+            self.pending.pop_front();
+        }
+        let in_flight: f64 = self.pending.iter().map(|&(_, correction)| correction).sum();
+        let error = drift - in_flight;
+        if error.abs() < THRESHOLD {
+            return 0.0;
+        }
+        let limit = duration * MAX_SPEED_CHANGE;
+        let correction = error.clamp(-limit, limit);
+        self.pending.push_back((pts + duration, correction));
+        correction
+    }
+
+    /// Forgets corrections in flight, e.g. after switching to the audio clock.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+    }
+}
@@ -0,0 +1,510 @@
+use std::f64::consts::PI;
+use std::str::FromStr;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+use parking_lot::Mutex;
+
+// crossfade between old and new settings so changes never click
+const FADE_SECONDS: f64 = 0.01;
+
+/// Centre frequencies of the ten-band graphic equalizer, one octave apart.
+pub const GRAPHIC_FREQUENCIES: [f64; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+// bandwidth of one octave
+const GRAPHIC_Q: f64 = 1.41;
+const SHELF_Q: f64 = 0.707;
+
+// night mode compressor: loud passages are pulled down towards dialog level, then everything
+// is raised so the average level stays about the same
+const NIGHT_THRESHOLD_DB: f64 = -24.0;
+const NIGHT_RATIO: f64 = 4.0;
+const NIGHT_MAKEUP_DB: f64 = 9.0;
+const NIGHT_ATTACK_SECONDS: f64 = 0.01;
+const NIGHT_RELEASE_SECONDS: f64 = 0.25;
+
+/// Direct form II transposed biquad.
+#[derive(Clone, Copy, Debug)]
+pub struct Biquad {
+    b: [f64; 3],
+    // a1 and a2, normalized by a0
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    pub fn from_coefficients(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self { b, a, z: [0.0; 2] }
+    }
+
+    /// RBJ audio EQ cookbook filter.
+    fn equalizer(band: &EqBand, sample_rate: f64) -> Self {
+        let a = 10f64.powf(band.gain_db / 40.0);
+        // keep the band below Nyquist, e.g. 16 kHz at a 32 kHz device rate
+        let frequency = band.frequency.min(sample_rate * 0.45);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * band.q);
+        let (b, a0, a1, a2) = match band.kind {
+            BandKind::Peak => (
+                [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            BandKind::LowShelf => {
+                let beta = 2.0 * a.sqrt() * alpha;
+                (
+                    [
+                        a * ((a + 1.0) - (a - 1.0) * cos + beta),
+                        2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                        a * ((a + 1.0) - (a - 1.0) * cos - beta),
+                    ],
+                    (a + 1.0) + (a - 1.0) * cos + beta,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - beta,
+                )
+            }
+            BandKind::HighShelf => {
+                let beta = 2.0 * a.sqrt() * alpha;
+                (
+                    [
+                        a * ((a + 1.0) + (a - 1.0) * cos + beta),
+                        -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                        a * ((a + 1.0) + (a - 1.0) * cos - beta),
+                    ],
+                    (a + 1.0) - (a - 1.0) * cos + beta,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - beta,
+                )
+            }
+        };
+        Self::from_coefficients(b.map(|b| b / a0), [a1 / a0, a2 / a0])
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BandKind {
+    Peak,
+    LowShelf,
+    HighShelf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EqBand {
+    pub kind: BandKind,
+    pub frequency: f64,
+    pub gain_db: f64,
+    pub q: f64,
+}
+
+/// A chain of equalizer bands, either the ten graphic bands or freely placed ones.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Equalizer {
+    pub bands: Vec<EqBand>,
+}
+
+impl Equalizer {
+    pub const PRESETS: [&'static str; 5] = ["flat", "bass", "treble", "vocal", "loudness"];
+
+    /// Ten-band graphic equalizer with gains in dB for [`GRAPHIC_FREQUENCIES`].
+    pub fn graphic(gains: [f64; 10]) -> Self {
+        let bands = GRAPHIC_FREQUENCIES
+            .iter()
+            .zip(gains)
+            .map(|(&frequency, gain_db)| EqBand {
+                kind: BandKind::Peak,
+                frequency,
+                gain_db,
+                q: GRAPHIC_Q,
+            })
+            .collect();
+        Self { bands }
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        let gains = match name {
+            "flat" => [0.0; 10],
+            "bass" => [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            "treble" => [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 3.0, 5.0, 6.0],
+            "vocal" => [-3.0, -2.0, -1.0, 0.0, 2.0, 3.0, 3.0, 2.0, 0.0, -1.0],
+            "loudness" => [5.0, 4.0, 2.0, 0.0, -1.0, -1.0, 0.0, 2.0, 4.0, 5.0],
+            _ => return None,
+        };
+        Some(Self::graphic(gains))
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.bands.iter().all(|band| band.gain_db == 0.0)
+    }
+}
+
+impl FromStr for Equalizer {
+    type Err = String;
+
+    /// Accepts a preset name, ten comma-separated graphic gains, or parametric bands like
+    /// `100:+3:0.7,low:80:+4,high:10000:-2` (`frequency:gain[:q]`, optionally as shelves).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(preset) = Self::preset(s) {
+            return Ok(preset);
+        }
+        let items: Vec<&str> = s.split(',').map(str::trim).collect();
+        let gains: Result<Vec<f64>, _> = items.iter().map(|gain| gain.parse()).collect();
+        if let Ok(gains) = gains
+            && let Ok(gains) = gains.try_into()
+        {
+            return Ok(Self::graphic(gains));
+        }
+
+        let parse_band = |item: &str| -> Option<EqBand> {
+            let mut parts: Vec<&str> = item.split(':').collect();
+            let kind = match parts.first() {
+                Some(&"low") => BandKind::LowShelf,
+                Some(&"high") => BandKind::HighShelf,
+                _ => BandKind::Peak,
+            };
+            if kind != BandKind::Peak {
+                parts.remove(0);
+            }
+            let default_q = if kind == BandKind::Peak {
+                GRAPHIC_Q
+            } else {
+                SHELF_Q
+            };
+            let (frequency, gain_db, q) = match parts[..] {
+                [f, g] => (f.parse().ok()?, g.parse().ok()?, default_q),
+                [f, g, q] => (f.parse().ok()?, g.parse().ok()?, q.parse().ok()?),
+                _ => return None,
+            };
+            (frequency > 0.0 && q > 0.0).then_some(EqBand {
+                kind,
+                frequency,
+                gain_db,
+                q,
+            })
+        };
+        items
+            .iter()
+            .map(|item| parse_band(item))
+            .collect::<Option<Vec<_>>>()
+            .map(|bands| Self { bands })
+            .ok_or_else(|| {
+                format!(
+                    "expected a preset ({}), ten gains, or bands like 100:+3:0.7, got '{}'",
+                    Self::PRESETS.join(", "),
+                    s
+                )
+            })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DspSettings {
+    /// Dynamic range compression for quiet listening
+    pub night_mode: bool,
+    pub equalizer: Equalizer,
+}
+
+impl DspSettings {
+    fn is_bypass(&self) -> bool {
+        !self.night_mode && self.equalizer.is_flat()
+    }
+}
+
+/// DSP settings shared between the player and the playback thread.
+pub struct DspControl {
+    settings: Mutex<DspSettings>,
+    // bumped on every change so the playback thread only locks when something changed
+    version: AtomicU64,
+}
+
+impl DspControl {
+    pub fn new(settings: DspSettings) -> Self {
+        Self {
+            settings: Mutex::new(settings),
+            version: AtomicU64::new(0),
+        }
+    }
+
+    pub fn settings(&self) -> DspSettings {
+        self.settings.lock().clone()
+    }
+
+    pub fn set(&self, settings: DspSettings) {
+        *self.settings.lock() = settings;
+        self.version.fetch_add(1, Ordering::Release);
+    }
+
+    fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+}
+
+/// Feed-forward compressor linked across channels.
+struct Compressor {
+    attack: f64,
+    release: f64,
+    envelope_db: f64,
+}
+
+impl Compressor {
+    fn new(sample_rate: f64) -> Self {
+        let coefficient = |seconds: f64| 1.0 - (-1.0 / (seconds * sample_rate)).exp();
+        Self {
+            attack: coefficient(NIGHT_ATTACK_SECONDS),
+            release: coefficient(NIGHT_RELEASE_SECONDS),
+            envelope_db: NIGHT_THRESHOLD_DB,
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        let peak = frame.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+        let level_db = 20.0 * (peak as f64).max(1e-9).log10();
+        let coefficient = if level_db > self.envelope_db {
+            self.attack
+        } else {
+            self.release
+        };
+        self.envelope_db += (level_db - self.envelope_db) * coefficient;
+
+        let over = (self.envelope_db - NIGHT_THRESHOLD_DB).max(0.0);
+        let gain_db = NIGHT_MAKEUP_DB - over * (1.0 - 1.0 / NIGHT_RATIO);
+        let gain = 10f64.powf(gain_db / 20.0) as f32;
+        for sample in frame.iter_mut() {
+            *sample *= gain;
+        }
+    }
+}
+
+/// One configuration of the DSP chain.
+struct Dsp {
+    channels: usize,
+    // per channel, one filter per band
+    filters: Vec<Vec<Biquad>>,
+    compressor: Option<Compressor>,
+}
+
+impl Dsp {
+    /// Returns `None` when the settings do nothing.
+    fn new(settings: &DspSettings, sample_rate: u32, channels: u16) -> Option<Self> {
+        if settings.is_bypass() {
+            return None;
+        }
+        let sample_rate = sample_rate as f64;
+        let bands: Vec<Biquad> = if settings.equalizer.is_flat() {
+            Vec::new()
+        } else {
+            settings
+                .equalizer
+                .bands
+                .iter()
+                .map(|band| Biquad::equalizer(band, sample_rate))
+                .collect()
+        };
+        let channels = channels.max(1) as usize;
+        Some(Self {
+            channels,
+            filters: vec![bands; channels],
+            compressor: settings.night_mode.then(|| Compressor::new(sample_rate)),
+        })
+    }
+
+    /// Takes over filter and envelope state so a parameter tweak continues the signal smoothly.
+    fn inherit(&mut self, previous: &Dsp) {
+        for (filters, old) in self.filters.iter_mut().zip(&previous.filters) {
+            if filters.len() == old.len() {
+                for (filter, old) in filters.iter_mut().zip(old) {
+                    filter.z = old.z;
+                }
+            }
+        }
+        if let (Some(compressor), Some(old)) = (self.compressor.as_mut(), &previous.compressor) {
+            compressor.envelope_db = old.envelope_db;
+        }
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(self.channels) {
+            for (sample, filters) in frame.iter_mut().zip(&mut self.filters) {
+                let mut x = *sample as f64;
+                for filter in filters.iter_mut() {
+                    x = filter.process(x);
+                }
+                *sample = x as f32;
+            }
+            if let Some(compressor) = self.compressor.as_mut() {
+                compressor.process(frame);
+            }
+        }
+    }
+}
+
+/// Playback-thread side of the DSP chain. Costs one atomic load per chunk while bypassed.
+pub struct DspStage {
+    control: Arc<DspControl>,
+    version: u64,
+    sample_rate: u32,
+    channels: u16,
+    current: Option<Dsp>,
+    // the chain being faded out and how many frames of the fade are done
+    fading: Option<(Option<Dsp>, usize)>,
+    fade_frames: usize,
+    old: Vec<f32>,
+}
+
+impl DspStage {
+    pub fn new(control: Arc<DspControl>, sample_rate: u32, channels: u16) -> Self {
+        let version = control.version();
+        let current = Dsp::new(&control.settings(), sample_rate, channels);
+        Self {
+            control,
+            version,
+            sample_rate,
+            channels,
+            current,
+            fading: None,
+            fade_frames: ((FADE_SECONDS * sample_rate as f64) as usize).max(1),
+            old: Vec::new(),
+        }
+    }
+
+    /// Processes interleaved samples in place, picking up changed settings.
+    pub fn process(&mut self, samples: &mut [f32]) {
+        let version = self.control.version();
+        if version != self.version {
+            self.version = version;
+            let mut next = Dsp::new(&self.control.settings(), self.sample_rate, self.channels);
+            if let (Some(next), Some(current)) = (next.as_mut(), self.current.as_ref()) {
+                next.inherit(current);
+            }
+            let previous = std::mem::replace(&mut self.current, next);
+            self.fading = Some((previous, 0));
+        }
+
+        let Some((previous, position)) = self.fading.as_mut() else {
+            if let Some(dsp) = self.current.as_mut() {
+                dsp.process(samples);
+            }
+            return;
+        };
+
+        // run both chains and crossfade from the old output to the new
+        self.old.clear();
+        self.old.extend_from_slice(samples);
+        if let Some(previous) = previous.as_mut() {
+            previous.process(&mut self.old);
+        }
+        if let Some(current) = self.current.as_mut() {
+            current.process(samples);
+        }
+        let channels = self.channels.max(1) as usize;
+        let frames = samples.chunks_exact_mut(channels);
+        for (frame, old) in frames.zip(self.old.chunks_exact(channels)) {
+            let t = (*position as f32 / self.fade_frames as f32).min(1.0);
+            for (sample, old) in frame.iter_mut().zip(old) {
+                *sample = old + (*sample - old) * t;
+            }
+            *position += 1;
+        }
+        if *position >= self.fade_frames {
+            self.fading = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_presets() {
+        for name in Equalizer::PRESETS {
+            assert_eq!(name.parse(), Ok(Equalizer::preset(name).unwrap()));
+        }
+        assert!("flat".parse::<Equalizer>().unwrap().is_flat());
+    }
+
+    #[test]
+    fn parses_ten_graphic_gains() {
+        let equalizer: Equalizer = "1, 2, 3, 4, 5, 0, -1, -2, -3, -4".parse().unwrap();
+        let gains: Vec<f64> = equalizer.bands.iter().map(|band| band.gain_db).collect();
+        assert_eq!(
+            gains,
+            [1.0, 2.0, 3.0, 4.0, 5.0, 0.0, -1.0, -2.0, -3.0, -4.0]
+        );
+        assert!(
+            equalizer
+                .bands
+                .iter()
+                .all(|band| band.kind == BandKind::Peak)
+        );
+    }
+
+    #[test]
+    fn parses_parametric_bands() {
+        let equalizer: Equalizer = "100:+3:0.7,low:80:+4,high:10000:-2:1.2".parse().unwrap();
+        assert_eq!(
+            equalizer.bands,
+            [
+                EqBand {
+                    kind: BandKind::Peak,
+                    frequency: 100.0,
+                    gain_db: 3.0,
+                    q: 0.7,
+                },
+                EqBand {
+                    kind: BandKind::LowShelf,
+                    frequency: 80.0,
+                    gain_db: 4.0,
+                    q: SHELF_Q,
+                },
+                EqBand {
+                    kind: BandKind::HighShelf,
+                    frequency: 10000.0,
+                    gain_db: -2.0,
+                    q: 1.2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn peak_bands_default_to_the_graphic_q() {
+        let equalizer: Equalizer = "1000:-6".parse().unwrap();
+        assert_eq!(equalizer.bands[0].q, GRAPHIC_Q);
+    }
+
+    #[test]
+    fn error_lists_the_presets_and_quotes_the_input() {
+        let error = "boom".parse::<Equalizer>().unwrap_err();
+        assert_eq!(
+            error,
+            format!(
+                "expected a preset ({}), ten gains, or bands like 100:+3:0.7, got 'boom'",
+                Equalizer::PRESETS.join(", ")
+            )
+        );
+    }
+
+    #[test]
+    fn one_bad_band_rejects_the_whole_list() {
+        // a zero frequency or Q would make the biquad unstable
+        let error = "low:80:+4,0:3".parse::<Equalizer>().unwrap_err();
+        assert!(error.ends_with("got 'low:80:+4,0:3'"), "{}", error);
+        let error = "100:3:0".parse::<Equalizer>().unwrap_err();
+        assert!(error.ends_with("got '100:3:0'"), "{}", error);
+        // fewer than ten plain gains are read as bands, which need a frequency and a gain
+        let error = "1,2,3".parse::<Equalizer>().unwrap_err();
+        assert!(error.ends_with("got '1,2,3'"), "{}", error);
+    }
+}
@@ -0,0 +1,99 @@
+use ffmpeg_next::{self as ffmpeg, util::frame::Audio as AudioFrame};
+use tracing::error;
+
+/// A libavfilter graph with a single audio input and output.
+pub struct AudioFilter {
+    graph: ffmpeg::filter::Graph,
+    time_base: ffmpeg::Rational,
+}
+
+impl AudioFilter {
+    /// Builds a filter graph for frames shaped like `frame`, whose timestamps are in `time_base`.
+    pub fn new(
+        spec: &str,
+        frame: &AudioFrame,
+        time_base: ffmpeg::Rational,
+    ) -> Result<Self, ffmpeg::Error> {
+        let mut graph = ffmpeg::filter::Graph::new();
+
+        // some demuxers leave the layout unspecified; fall back to the default for the channel count
+        let layout = frame.channel_layout();
+        let layout = if layout.bits() != 0 {
+            layout
+        } else {
+            ffmpeg::ChannelLayout::default(frame.channels() as i32)
+        };
+        let args = format!(
+            "time_base={}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+            time_base,
+            frame.rate(),
+            frame.format().name(),
+            layout.bits(),
+        );
+
+        graph.add(
+            &ffmpeg::filter::find("abuffer").ok_or(ffmpeg::Error::FilterNotFound)?,
+            "in",
+            &args,
+        )?;
+        graph.add(
+            &ffmpeg::filter::find("abuffersink").ok_or(ffmpeg::Error::FilterNotFound)?,
+            "out",
+            "",
+        )?;
+        graph.output("in", 0)?.input("out", 0)?.parse(spec)?;
+        graph.validate()?;
+
+        // resampling filters change the output time base
+        let time_base = graph.get("out").unwrap().sink().time_base();
+
+        Ok(Self { graph, time_base })
+    }
+
+    /// Time base of frames returned by [`AudioFilter::pull`].
+    pub fn time_base(&self) -> ffmpeg::Rational {
+        self.time_base
+    }
+
+    /// Feeds a decoded frame into the graph.
+    pub fn push(&mut self, frame: &AudioFrame) -> Result<(), ffmpeg::Error> {
+        self.graph.get("in").unwrap().source().add(frame)
+    }
+
+    /// Signals end of stream so buffered samples can be drained.
+    pub fn flush(&mut self) -> Result<(), ffmpeg::Error> {
+        self.graph.get("in").unwrap().source().flush()
+    }
+
+    /// Returns the next filtered frame, or None if the graph needs more input.
+    pub fn pull(&mut self) -> Option<AudioFrame> {
+        let mut filtered = AudioFrame::empty();
+        match self.graph.get("out").unwrap().sink().frame(&mut filtered) {
+            Ok(()) => Some(filtered),
+            Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::sys::EAGAIN => None,
+            Err(ffmpeg::Error::Eof) => None,
+            Err(e) => {
+                error!("audio filter error: {:?}", e);
+                None
+            }
+        }
+    }
+}
+
+/// `atempo` chain changing the speed by `speed` without changing the pitch, or None at 1x.
+///
+/// Each stage is kept within 0.5x to 2x, where atempo sounds best.
+pub fn tempo_spec(speed: f64) -> Option<String> {
+    if (speed - 1.0).abs() < 1e-6 {
+        return None;
+    }
+    let mut stages = Vec::new();
+    let mut rest = speed;
+    while rest > 2.0 || rest < 0.5 {
+        let stage = if rest > 2.0 { 2.0 } else { 0.5 };
+        stages.push(format!("atempo={}", stage));
+        rest /= stage;
+    }
+    stages.push(format!("atempo={}", rest));
+    Some(stages.join(","))
+}
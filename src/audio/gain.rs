@@ -0,0 +1,181 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use crate::audio::loudness::TruePeakLimiter;
+
+// time for a full-scale gain change, long enough to avoid clicks and short enough to feel instant
+const RAMP_SECONDS: f32 = 0.02;
+
+/// Upper bound for the volume setting, in percent.
+pub const MAX_VOLUME: f32 = 150.0;
+
+/// Maps a volume percentage to decibels: 60 dB per decade, so 100% is unity gain,
+/// 50% is about -18 dB and 150% is about +10.6 dB.
+pub fn volume_to_db(percent: f32) -> f32 {
+    60.0 * (percent / 100.0).log10()
+}
+
+/// Linear gain for a volume percentage, silent at 0%.
+pub fn volume_to_gain(percent: f32) -> f32 {
+    if percent <= 0.0 {
+        return 0.0;
+    }
+    10f32.powf(volume_to_db(percent) / 20.0)
+}
+
+/// Gain settings shared between the player and the audio callback.
+pub struct GainControl {
+    // f32 bits of the linear target gain derived from the volume, up to unity
+    gain: AtomicU32,
+    // f32 bits of the gain above unity, applied by a `VolumeBoost` ahead of the callback
+    boost: AtomicU32,
+    muted: AtomicBool,
+    paused: AtomicBool,
+}
+
+impl GainControl {
+    pub fn new(volume: f32, muted: bool) -> Self {
+        let gain = volume_to_gain(volume);
+        Self {
+            gain: AtomicU32::new(gain.min(1.0).to_bits()),
+            boost: AtomicU32::new(gain.max(1.0).to_bits()),
+            muted: AtomicBool::new(muted),
+            paused: AtomicBool::new(false),
+        }
+    }
+
+    pub fn set_volume(&self, percent: f32) {
+        let gain = volume_to_gain(percent);
+        self.gain.store(gain.min(1.0).to_bits(), Ordering::Relaxed);
+        self.boost.store(gain.max(1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    fn boost(&self) -> f32 {
+        f32::from_bits(self.boost.load(Ordering::Relaxed))
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Fades out and holds buffered audio while paused, then fades back in.
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    fn target(&self) -> f32 {
+        if self.paused.load(Ordering::Relaxed) || self.muted.load(Ordering::Relaxed) {
+            0.0
+        } else {
+            f32::from_bits(self.gain.load(Ordering::Relaxed))
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+}
+
+/// Callback-side gain state that ramps towards the [`GainControl`] target.
+pub struct GainRamp {
+    current: f32,
+    // gain change per frame for a full-scale ramp
+    step: f32,
+    channels: usize,
+}
+
+impl GainRamp {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            current: 0.0,
+            step: 1.0 / (RAMP_SECONDS * sample_rate as f32),
+            channels: channels.max(1) as usize,
+        }
+    }
+
+    /// True once a pause fade has finished, so the callback can stop consuming samples.
+    pub fn is_silent(&self, control: &GainControl) -> bool {
+        self.current == 0.0 && control.is_paused()
+    }
+
+    /// True once the gain has ramped all the way down, e.g. by [`GainRamp::fade_out`].
+    pub fn is_faded(&self) -> bool {
+        self.current == 0.0
+    }
+
+    /// Ramps to silence whatever the target, so audio can be dropped without a click;
+    /// [`GainRamp::apply`] ramps back up afterwards.
+    pub fn fade_out(&mut self, data: &mut [f32]) {
+        for frame in data.chunks_mut(self.channels) {
+            self.current = (self.current - self.step).max(0.0);
+            for sample in frame {
+                *sample = (*sample * self.current).clamp(-1.0, 1.0);
+            }
+        }
+    }
+
+    /// Applies the gain to interleaved samples, ramping per frame so all channels move together.
+    pub fn apply(&mut self, control: &GainControl, data: &mut [f32]) {
+        let target = control.target();
+        if self.current == target {
+            if target != 1.0 {
+                for sample in data.iter_mut() {
+                    *sample = (*sample * target).clamp(-1.0, 1.0);
+                }
+            }
+            return;
+        }
+
+        for frame in data.chunks_mut(self.channels) {
+            if self.current < target {
+                self.current = (self.current + self.step).min(target);
+            } else {
+                self.current = (self.current - self.step).max(target);
+            }
+            for sample in frame {
+                *sample = (*sample * self.current).clamp(-1.0, 1.0);
+            }
+        }
+    }
+}
+
+/// Volume above 100%, applied before the ring buffer so a limiter can keep the boosted peaks
+/// under full scale instead of the callback clipping them; changes to it are heard once the
+/// audio already buffered has played.
+pub struct VolumeBoost {
+    current: f32,
+    limiter: TruePeakLimiter,
+    channels: usize,
+    sample_rate: f64,
+}
+
+impl VolumeBoost {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            current: 1.0,
+            // only overs are touched, so audio at 100% passes unchanged
+            limiter: TruePeakLimiter::new(sample_rate, channels, 0.0),
+            channels: channels.max(1) as usize,
+            sample_rate: sample_rate.max(1) as f64,
+        }
+    }
+
+    /// How much later audio leaves the limiter than it enters, in seconds.
+    pub fn latency(&self) -> f64 {
+        self.limiter.delay_frames() as f64 / self.sample_rate
+    }
+
+    /// Boosts and limits interleaved samples in place, ramping to a new boost over the chunk.
+    pub fn process(&mut self, control: &GainControl, data: &mut [f32]) {
+        let target = control.boost();
+        let frames = (data.len() / self.channels).max(1);
+        let step = (target - self.current) / frames as f32;
+        for frame in data.chunks_mut(self.channels) {
+            self.current += step;
+            for sample in frame.iter_mut() {
+                *sample *= self.current;
+            }
+            self.limiter.process(frame);
+        }
+        self.current = target;
+    }
+}
@@ -0,0 +1,477 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::audio::{decoder::AudioDecoder, dsp::Biquad};
+
+// ReplayGain 2.0 reference level; tagged gains bring a track to this loudness
+const REPLAYGAIN_REFERENCE: f32 = -18.0; // LUFS
+// Opus R128 gain tags are relative to EBU R128's -23 LUFS instead
+const R128_REFERENCE: f32 = -23.0; // LUFS
+// bounds for the real-time normalizer, so silence or a quiet intro is not boosted into noise
+const MAX_BOOST: f64 = 12.0; // dB
+const MAX_CUT: f64 = 24.0; // dB
+// gain slew of the real-time normalizer; slow enough to be inaudible
+const SLEW_DB_PER_SECOND: f64 = 3.0;
+// limiter ceiling, leaving headroom for lossy encoders and DACs as EBU R128 recommends
+const CEILING_DB: f32 = -1.0; // dBTP
+const LOOKAHEAD_SECONDS: f64 = 0.005;
+const RELEASE_SECONDS: f64 = 0.1;
+// block loudness histogram as in libebur128: 0.1 LU bins from the absolute gate up to +30 LUFS
+const HISTOGRAM_FLOOR: f64 = -70.0; // LUFS
+const HISTOGRAM_BINS: usize = 1000;
+
+/// Which ReplayGain tag, if any, sets the playback gain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    /// Per-track gain, falling back to the album gain
+    Track,
+    /// Per-album gain, preserving level differences between tracks, falling back to the track gain
+    Album,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoudnessOptions {
+    pub replay_gain: ReplayGainMode,
+    /// Measure EBU R128 loudness while playing and steer towards `target` when no tag applies
+    pub normalize: bool,
+    /// Target loudness in LUFS
+    pub target: f32,
+}
+
+impl Default for LoudnessOptions {
+    fn default() -> Self {
+        Self {
+            replay_gain: ReplayGainMode::Off,
+            normalize: false,
+            target: REPLAYGAIN_REFERENCE,
+        }
+    }
+}
+
+/// Reads the gain in dB for `mode` from the file tags, relative to a target of `target` LUFS.
+pub fn replay_gain(decoder: &AudioDecoder, mode: ReplayGainMode, target: f32) -> Option<f32> {
+    let (first, second) = match mode {
+        ReplayGainMode::Off => return None,
+        ReplayGainMode::Track => ("TRACK", "ALBUM"),
+        ReplayGainMode::Album => ("ALBUM", "TRACK"),
+    };
+    let replay_gain = |kind: &str| {
+        let tag = decoder.tag(&format!("REPLAYGAIN_{}_GAIN", kind))?;
+        // values look like "-6.48 dB"
+        let gain: f32 = tag.trim().trim_end_matches("dB").trim().parse().ok()?;
+        Some(gain + target - REPLAYGAIN_REFERENCE)
+    };
+    let r128 = |kind: &str| {
+        // Q7.8 fixed point dB
+        let gain: i32 = decoder
+            .tag(&format!("R128_{}_GAIN", kind))?
+            .trim()
+            .parse()
+            .ok()?;
+        Some(gain as f32 / 256.0 + target - R128_REFERENCE)
+    };
+    replay_gain(first)
+        .or_else(|| r128(first))
+        .or_else(|| replay_gain(second))
+        .or_else(|| r128(second))
+}
+
+/// Loudness stage between the resampler and the ring buffer: a fixed ReplayGain or a
+/// real-time R128 normalizer, followed by a true-peak limiter.
+pub struct Loudness {
+    sample_rate: f64,
+    channels: usize,
+    // normalizer gain in dB and where it is heading
+    gain_db: f64,
+    target_gain_db: f64,
+    gain: f32,
+    meter: Option<(LoudnessMeter, f64)>,
+    limiter: TruePeakLimiter,
+}
+
+impl Loudness {
+    /// Returns `None` when neither a tag gain nor normalization applies, so it costs nothing.
+    /// The normalizer measures into `history`, picking up where an earlier stage left off.
+    pub fn new(
+        options: &LoudnessOptions,
+        tag_gain: Option<f32>,
+        history: Arc<LoudnessHistory>,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Option<Self> {
+        let target = options.target as f64;
+        let (meter, gain_db) = match tag_gain {
+            Some(gain) => (None, gain as f64),
+            None if options.normalize => {
+                // after a seek, continue at the gain already measured instead of ramping to it
+                let gain_db = history
+                    .integrated()
+                    .map_or(0.0, |integrated| normalizer_gain(target, integrated));
+                let meter = LoudnessMeter::new(sample_rate, channels, history);
+                (Some((meter, target)), gain_db)
+            }
+            None => return None,
+        };
+        Some(Self {
+            sample_rate: sample_rate as f64,
+            channels: channels.max(1) as usize,
+            gain_db,
+            target_gain_db: gain_db,
+            gain: db_to_gain(gain_db),
+            meter,
+            limiter: TruePeakLimiter::new(sample_rate, channels, CEILING_DB),
+        })
+    }
+
+    /// How much later audio leaves this stage than it enters, in seconds.
+    pub fn latency(&self) -> f64 {
+        self.limiter.delay_frames() as f64 / self.sample_rate
+    }
+
+    /// Normalizes and limits interleaved samples in place.
+    pub fn process(&mut self, samples: &mut [f32]) {
+        let slew = SLEW_DB_PER_SECOND / self.sample_rate;
+        for frame in samples.chunks_exact_mut(self.channels) {
+            if let Some((meter, target)) = self.meter.as_mut()
+                && meter.push(frame)
+                && let Some(integrated) = meter.history.integrated()
+            {
+                self.target_gain_db = normalizer_gain(*target, integrated);
+            }
+            if self.gain_db != self.target_gain_db {
+                self.gain_db += (self.target_gain_db - self.gain_db).clamp(-slew, slew);
+                self.gain = db_to_gain(self.gain_db);
+            }
+
+            for sample in frame.iter_mut() {
+                *sample *= self.gain;
+            }
+            self.limiter.process(frame);
+        }
+    }
+}
+
+/// Gain in dB bringing `integrated` LUFS to `target`, within the normalizer's bounds.
+fn normalizer_gain(target: f64, integrated: f64) -> f64 {
+    (target - integrated).clamp(-MAX_CUT, MAX_BOOST)
+}
+
+fn db_to_gain(db: f64) -> f32 {
+    10f64.powf(db / 20.0) as f32
+}
+
+/// ITU-R BS.1770 K-weighting (high shelf followed by high-pass), designed for any sample rate
+/// with the same analog prototypes as libebur128.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let g = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / fs).tan();
+    let vh = 10f64.powf(g / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::from_coefficients(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::from_coefficients(
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+/// BS.1770 channel weights for FFmpeg's default layouts: surrounds count 1.41, LFE not at all.
+fn channel_weights(channels: usize) -> Vec<f64> {
+    // quad has no center; 5.1 and larger layouts put the LFE fourth
+    let fronts = if channels == 4 { 2 } else { 3 };
+    let lfe = (channels >= 6).then_some(3);
+    (0..channels)
+        .map(|channel| {
+            if Some(channel) == lfe {
+                0.0
+            } else if channel < fronts {
+                1.0
+            } else {
+                1.41
+            }
+        })
+        .collect()
+}
+
+/// Gated block loudness of the track played so far, kept across seeks so normalization does
+/// not start over.
+pub struct LoudnessHistory {
+    inner: Mutex<Histogram>,
+}
+
+struct Histogram {
+    // blocks per 0.1 LU bin above the absolute gate
+    counts: Vec<u64>,
+    // exact sum and count of the blocks, for the relative gate
+    energy: f64,
+    blocks: u64,
+}
+
+impl LoudnessHistory {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(Histogram {
+                counts: vec![0; HISTOGRAM_BINS],
+                energy: 0.0,
+                blocks: 0,
+            }),
+        }
+    }
+
+    /// Forgets the measurement, e.g. when another track starts playing.
+    pub fn clear(&self) {
+        let mut histogram = self.inner.lock();
+        histogram.counts.fill(0);
+        histogram.energy = 0.0;
+        histogram.blocks = 0;
+    }
+
+    fn add(&self, block: f64) {
+        let lufs = energy_to_lufs(block);
+        // absolute gate at -70 LUFS
+        if lufs <= HISTOGRAM_FLOOR {
+            return;
+        }
+        let mut histogram = self.inner.lock();
+        histogram.counts[histogram_bin(lufs)] += 1;
+        histogram.energy += block;
+        histogram.blocks += 1;
+    }
+
+    /// Integrated loudness in LUFS, in time bounded by the histogram size however long the
+    /// track has played.
+    fn integrated(&self) -> Option<f64> {
+        let histogram = self.inner.lock();
+        if histogram.blocks == 0 {
+            return None;
+        }
+        // relative gate 10 LU below the absolute-gated loudness
+        let gate = energy_to_lufs(histogram.energy / histogram.blocks as f64) - 10.0;
+        let start = histogram_bin(gate);
+        let (sum, count) = (start..)
+            .zip(&histogram.counts[start..])
+            .fold((0.0, 0u64), |(sum, count), (bin, &blocks)| {
+                (sum + blocks as f64 * bin_energy(bin), count + blocks)
+            });
+        (count > 0).then(|| energy_to_lufs(sum / count as f64))
+    }
+}
+
+impl Default for LoudnessHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn histogram_bin(lufs: f64) -> usize {
+    (((lufs - HISTOGRAM_FLOOR) * 10.0).max(0.0) as usize).min(HISTOGRAM_BINS - 1)
+}
+
+/// Mean square energy at the center of histogram bin `bin`.
+fn bin_energy(bin: usize) -> f64 {
+    lufs_to_energy(HISTOGRAM_FLOOR + (bin as f64 + 0.5) / 10.0)
+}
+
+/// Measures EBU R128 blocks into a [`LoudnessHistory`].
+struct LoudnessMeter {
+    weights: Vec<f64>,
+    filters: Vec<[Biquad; 2]>,
+    // 100 ms sub-blocks; four of them make one 400 ms measurement block with 75% overlap
+    sub_block_frames: usize,
+    frames: usize,
+    energy: f64,
+    recent: VecDeque<f64>,
+    history: Arc<LoudnessHistory>,
+}
+
+impl LoudnessMeter {
+    fn new(sample_rate: u32, channels: u16, history: Arc<LoudnessHistory>) -> Self {
+        let channels = channels.max(1) as usize;
+        Self {
+            weights: channel_weights(channels),
+            filters: vec![k_weighting(sample_rate); channels],
+            sub_block_frames: (sample_rate as usize / 10).max(1),
+            frames: 0,
+            energy: 0.0,
+            recent: VecDeque::with_capacity(4),
+            history,
+        }
+    }
+
+    /// Measures one frame; returns true when a measurement block completed.
+    fn push(&mut self, frame: &[f32]) -> bool {
+        for ((sample, filters), weight) in frame.iter().zip(&mut self.filters).zip(&self.weights) {
+            let filtered = filters[1].process(filters[0].process(*sample as f64));
+            self.energy += weight * filtered * filtered;
+        }
+        self.frames += 1;
+        if self.frames < self.sub_block_frames {
+            return false;
+        }
+
+        if self.recent.len() == 4 {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(self.energy / self.frames as f64);
+        self.frames = 0;
+        self.energy = 0.0;
+        if self.recent.len() < 4 {
+            return false;
+        }
+
+        self.history.add(self.recent.iter().sum::<f64>() / 4.0);
+        true
+    }
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.max(1e-20).log10()
+}
+
+fn lufs_to_energy(lufs: f64) -> f64 {
+    10f64.powf((lufs + 0.691) / 10.0)
+}
+
+// interpolation taps per phase of the true-peak detector
+const PEAK_TAPS: usize = 8;
+// frames between a sample entering the detector and the peaks around it being known
+const PEAK_DELAY: usize = PEAK_TAPS / 2;
+
+/// Look-ahead limiter on 4x oversampled peaks, keeping inter-sample peaks under the ceiling.
+pub struct TruePeakLimiter {
+    channels: usize,
+    ceiling: f32,
+    window: usize,
+    // windowed-sinc coefficients for the three points between two input samples
+    phases: [[f32; PEAK_TAPS]; 3],
+    history: Vec<[f32; PEAK_TAPS]>,
+    // interleaved delay line, so gain reductions land before the peaks they are for
+    delay: VecDeque<f32>,
+    // (frame, required gain) in increasing gain order, for a sliding minimum over the window
+    minimum: VecDeque<(u64, f32)>,
+    held: f32,
+    release: f32,
+    // moving average of the held gain over the window, so reductions ramp in smoothly
+    average: VecDeque<f32>,
+    sum: f64,
+    frame: u64,
+}
+
+impl TruePeakLimiter {
+    pub fn new(sample_rate: u32, channels: u16, ceiling_db: f32) -> Self {
+        let channels = channels.max(1) as usize;
+        let window = ((LOOKAHEAD_SECONDS * sample_rate as f64) as usize).max(1);
+        let mut phases = [[0.0; PEAK_TAPS]; 3];
+        for (phase, taps) in phases.iter_mut().enumerate() {
+            // points at 1/4, 2/4 and 3/4 between history[PEAK_DELAY - 1] and history[PEAK_DELAY]
+            let position = (PEAK_DELAY - 1) as f64 + (phase + 1) as f64 / 4.0;
+            for (tap, coefficient) in taps.iter_mut().enumerate() {
+                let x = position - tap as f64;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * x).sin() / (PI * x)
+                };
+                let hann = 0.5 * (1.0 + (PI * x / PEAK_DELAY as f64).cos());
+                *coefficient = (sinc * hann) as f32;
+            }
+        }
+        let delay_frames = window + PEAK_DELAY - 1;
+        Self {
+            channels,
+            ceiling: 10f32.powf(ceiling_db / 20.0),
+            window,
+            phases,
+            history: vec![[0.0; PEAK_TAPS]; channels],
+            delay: VecDeque::from(vec![0.0; delay_frames * channels]),
+            minimum: VecDeque::new(),
+            held: 1.0,
+            release: (1.0 - (-1.0 / (RELEASE_SECONDS * sample_rate as f64)).exp()) as f32,
+            average: VecDeque::from(vec![1.0; window]),
+            sum: window as f64,
+            frame: 0,
+        }
+    }
+
+    pub fn delay_frames(&self) -> usize {
+        self.delay.len() / self.channels
+    }
+
+    /// Replaces one frame with the delayed, limited frame.
+    pub fn process(&mut self, frame: &mut [f32]) {
+        let mut peak = 0.0_f32;
+        for (sample, history) in frame.iter().zip(&mut self.history) {
+            history.copy_within(1.., 0);
+            history[PEAK_TAPS - 1] = *sample;
+            peak = peak.max(history[PEAK_DELAY - 1].abs());
+            for taps in &self.phases {
+                let value: f32 = taps.iter().zip(history.iter()).map(|(c, x)| c * x).sum();
+                peak = peak.max(value.abs());
+            }
+        }
+        let required = if peak > self.ceiling {
+            self.ceiling / peak
+        } else {
+            1.0
+        };
+
+        // sliding minimum over the window
+        while self
+            .minimum
+            .back()
+            .is_some_and(|&(_, gain)| gain >= required)
+        {
+            self.minimum.pop_back();
+        }
+        self.minimum.push_back((self.frame, required));
+        while self
+            .minimum
+            .front()
+            .is_some_and(|&(frame, _)| frame + self.window as u64 <= self.frame)
+        {
+            self.minimum.pop_front();
+        }
+        self.frame += 1;
+        let minimum = self.minimum.front().map_or(1.0, |&(_, gain)| gain);
+
+        // instant attack, exponential release
+        if minimum < self.held {
+            self.held = minimum;
+        } else {
+            self.held += (minimum - self.held) * self.release;
+        }
+        self.sum += self.held as f64 - self.average.pop_front().unwrap_or(1.0) as f64;
+        self.average.push_back(self.held);
+        let gain = (self.sum / self.window as f64) as f32;
+
+        self.delay.extend(frame.iter().copied());
+        for sample in frame.iter_mut() {
+            let delayed = self.delay.pop_front().unwrap_or(0.0);
+            // the smoothed gain covers detected peaks; the clamp only catches rounding
+            *sample = (delayed * gain).clamp(-self.ceiling, self.ceiling);
+        }
+    }
+}
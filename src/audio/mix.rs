@@ -0,0 +1,218 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
+
+use crate::audio::gain::volume_to_gain;
+
+// how much the main track is lowered while the secondary one is talking
+const DUCK_GAIN: f32 = 0.3; // about -10 dB
+// level above which the secondary track counts as active
+const DUCK_THRESHOLD: f32 = 0.01; // -40 dBFS
+// envelope follower of the secondary level
+const DETECT_ATTACK: f32 = 0.005; // seconds
+const DETECT_RELEASE: f32 = 0.3; // seconds
+// main track gain moves down quickly so speech is not masked, and back up slowly
+const DUCK_ATTACK: f32 = 0.05; // seconds
+const DUCK_RELEASE: f32 = 0.6; // seconds
+// secondary audio this far off the main timeline is realigned instead of played in sequence
+const RESYNC_THRESHOLD: f64 = 0.05; // seconds
+// longest wait for the secondary decoder to catch up before mixing silence
+const DECODE_WAIT: Duration = Duration::from_millis(50);
+
+/// Volumes of the main and secondary tracks and ducking, shared with the playback thread.
+pub struct MixControl {
+    // f32 bits of the linear gains derived from the volumes
+    main: AtomicU32,
+    secondary: AtomicU32,
+    ducking: AtomicBool,
+}
+
+impl MixControl {
+    pub fn new(main_volume: f32, secondary_volume: f32, ducking: bool) -> Self {
+        Self {
+            main: AtomicU32::new(volume_to_gain(main_volume).to_bits()),
+            secondary: AtomicU32::new(volume_to_gain(secondary_volume).to_bits()),
+            ducking: AtomicBool::new(ducking),
+        }
+    }
+
+    pub fn set_main_volume(&self, percent: f32) {
+        self.main
+            .store(volume_to_gain(percent).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn set_secondary_volume(&self, percent: f32) {
+        self.secondary
+            .store(volume_to_gain(percent).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn set_ducking(&self, enabled: bool) {
+        self.ducking.store(enabled, Ordering::Relaxed);
+    }
+
+    fn gains(&self) -> (f32, f32) {
+        (
+            f32::from_bits(self.main.load(Ordering::Relaxed)),
+            f32::from_bits(self.secondary.load(Ordering::Relaxed)),
+        )
+    }
+}
+
+/// Playback-side state mixing a secondary track, such as a commentary, into the main one.
+pub struct SecondaryMix {
+    receiver: flume::Receiver<(Vec<f32>, f64, u32, u16)>,
+    // decoded samples not mixed yet, starting at `pts`
+    pending: VecDeque<f32>,
+    pts: f64,
+    // the secondary track is shifted by this much more than the main one
+    offset: f64,
+    ended: bool,
+    // frames per second of media time, lower than the sample rate when playing faster
+    media_rate: f64,
+    channels: usize,
+    // gains applied at the end of the previous chunk, ramped from to avoid zipper noise
+    main_gain: f32,
+    secondary_gain: f32,
+    envelope: f32,
+    duck: f32,
+    detect_attack: f32,
+    detect_release: f32,
+    duck_attack: f32,
+    duck_release: f32,
+}
+
+/// Per-sample smoothing coefficient for a time constant in seconds.
+fn coefficient(seconds: f32, sample_rate: u32) -> f32 {
+    1.0 - (-1.0 / (seconds * sample_rate as f32)).exp()
+}
+
+impl SecondaryMix {
+    pub fn new(
+        receiver: flume::Receiver<(Vec<f32>, f64, u32, u16)>,
+        offset: f64,
+        sample_rate: u32,
+        channels: u16,
+        speed: f64,
+    ) -> Self {
+        Self {
+            receiver,
+            pending: VecDeque::new(),
+            pts: 0.0,
+            offset,
+            ended: false,
+            media_rate: sample_rate as f64 / speed,
+            channels: channels.max(1) as usize,
+            main_gain: 1.0,
+            secondary_gain: 1.0,
+            envelope: 0.0,
+            duck: 1.0,
+            detect_attack: coefficient(DETECT_ATTACK, sample_rate),
+            detect_release: coefficient(DETECT_RELEASE, sample_rate),
+            duck_attack: coefficient(DUCK_ATTACK, sample_rate),
+            duck_release: coefficient(DUCK_RELEASE, sample_rate),
+        }
+    }
+
+    /// Mixes the secondary audio belonging to the main chunk `samples` starting at `pts`.
+    pub fn mix_into(&mut self, control: &MixControl, samples: &mut [f32], pts: f64) {
+        let frames = samples.len() / self.channels;
+        let secondary = self.take(pts, frames);
+        let (main_target, secondary_target) = control.gains();
+        let ducking = control.ducking.load(Ordering::Relaxed);
+
+        for (index, (frame, other)) in samples
+            .chunks_exact_mut(self.channels)
+            .zip(secondary.chunks_exact(self.channels))
+            .enumerate()
+        {
+            let level = other.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+            let detect = if level > self.envelope {
+                self.detect_attack
+            } else {
+                self.detect_release
+            };
+            self.envelope += (level - self.envelope) * detect;
+
+            let duck_target = if ducking && self.envelope > DUCK_THRESHOLD {
+                DUCK_GAIN
+            } else {
+                1.0
+            };
+            let rate = if duck_target < self.duck {
+                self.duck_attack
+            } else {
+                self.duck_release
+            };
+            self.duck += (duck_target - self.duck) * rate;
+
+            let t = (index + 1) as f32 / frames as f32;
+            let main_gain = self.main_gain + (main_target - self.main_gain) * t;
+            let secondary_gain = self.secondary_gain + (secondary_target - self.secondary_gain) * t;
+            for (sample, &other) in frame.iter_mut().zip(other) {
+                *sample = *sample * main_gain * self.duck + other * secondary_gain;
+            }
+        }
+        self.main_gain = main_target;
+        self.secondary_gain = secondary_target;
+    }
+
+    /// The `frames` secondary frames playing alongside main audio at `pts`, silence where
+    /// there is none.
+    fn take(&mut self, pts: f64, frames: usize) -> Vec<f32> {
+        // the secondary timeline expressed on the main one
+        let pts = pts - self.offset;
+        let end = pts + frames as f64 / self.media_rate;
+        while !self.ended && self.pending_end() < end {
+            match self.receiver.recv_timeout(DECODE_WAIT) {
+                Ok((chunk, chunk_pts, _, _)) => {
+                    let expected = self.pending_end();
+                    if self.pending.is_empty() || (chunk_pts - expected).abs() > RESYNC_THRESHOLD {
+                        self.pending.clear();
+                        self.pts = chunk_pts;
+                    }
+                    self.pending.extend(chunk);
+                    // entirely before this chunk, e.g. after main chunks were dropped as late
+                    if self.pending_end() <= pts {
+                        self.pts = self.pending_end();
+                        self.pending.clear();
+                    }
+                }
+                Err(flume::RecvTimeoutError::Timeout) => break,
+                Err(flume::RecvTimeoutError::Disconnected) => self.ended = true,
+            }
+        }
+
+        let mut out = vec![0.0; frames * self.channels];
+        let skew = ((pts - self.pts) * self.media_rate).round() as i64;
+        if skew.unsigned_abs() as f64 > RESYNC_THRESHOLD * self.media_rate {
+            if skew > 0 {
+                // behind the main track, e.g. after dropped late chunks: skip ahead
+                let drop = (skew as usize * self.channels).min(self.pending.len());
+                self.pending.drain(..drop);
+                self.pts += (drop / self.channels) as f64 / self.media_rate;
+            } else {
+                // starts later than this chunk: leave a gap of silence before it
+                let gap = (-skew as usize).min(frames);
+                let rest = &mut out[gap * self.channels..];
+                let count = rest.len().min(self.pending.len());
+                for (slot, sample) in rest.iter_mut().zip(self.pending.drain(..count)) {
+                    *slot = sample;
+                }
+                self.pts += (count / self.channels) as f64 / self.media_rate;
+                return out;
+            }
+        }
+
+        // close enough: play in sequence so small differences do not cause clicks
+        let count = out.len().min(self.pending.len());
+        for (slot, sample) in out.iter_mut().zip(self.pending.drain(..count)) {
+            *slot = sample;
+        }
+        self.pts += (count / self.channels) as f64 / self.media_rate;
+        out
+    }
+
+    fn pending_end(&self) -> f64 {
+        self.pts + (self.pending.len() / self.channels) as f64 / self.media_rate
+    }
+}
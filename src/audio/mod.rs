@@ -1,2 +1,14 @@
+pub mod channels;
 pub mod decoder;
+pub mod drift;
+pub mod dsp;
+pub mod filter;
+pub mod gain;
+pub mod loudness;
+pub mod mix;
 pub mod player;
+pub mod resample;
+pub mod sink;
+pub mod stats;
+pub mod tap;
+pub mod track;
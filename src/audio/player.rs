@@ -1,28 +1,832 @@
-use crate::media::clock::GlobalClock;
+use crate::media::clock::{ClockMode, GlobalClock};
 use std::sync::{
     Arc,
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    mpsc,
 };
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use flume;
-use tracing::error;
+use tracing::{error, info, warn};
 
-use crate::audio::decoder::AudioDecoder;
-use ffmpeg_next as ffmpeg;
+use crate::audio::{
+    channels::{ChannelCount, ChannelMap, DownmixMode},
+    decoder::AudioDecoder,
+    drift::{DriftCompensator, DriftMonitor, MAX_COMPENSATED},
+    dsp::{DspControl, DspSettings, DspStage},
+    filter::{AudioFilter, tempo_spec},
+    gain::{GainControl, GainRamp, VolumeBoost},
+    loudness::{Loudness, LoudnessHistory, LoudnessOptions, replay_gain},
+    mix::{MixControl, SecondaryMix},
+    resample::{DitherMode, ResamplerSettings},
+    sink::{AudioSink, SinkKind, SinkRequest, open_sink},
+    stats::{AdaptiveLatency, AudioCounters, AudioStats, MAX_LATENCY},
+    tap::PcmTap,
+    track::AudioTrack,
+};
+use ffmpeg_next::{self as ffmpeg, util::frame::Audio as AudioFrame};
 use ringbuf::RingBuffer;
 
+// how often the supervisor checks for a lost device while idle
+const SUPERVISE_INTERVAL: Duration = Duration::from_millis(200);
+// wait between attempts to open an output device after a failure
+const REOPEN_INTERVAL: Duration = Duration::from_secs(2);
+// device buffer requested in low-latency mode unless a size is given
+const LOW_LATENCY_BUFFER: u32 = 256; // frames
+/// Set once the user has been told that soxr cannot compensate drift.
+static SOXR_COMPENSATION_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Options fixed at the time audio playback is spawned.
+#[derive(Clone, Debug)]
+pub struct AudioOptions {
+    /// libavfilter graph inserted between the decoder and the resampler, e.g. `loudnorm`
+    pub filter: Option<String>,
+    /// Offset added to audio timestamps in seconds; positive plays audio later
+    pub delay: f64,
+    /// Volume in percent, from 0 to [`gain::MAX_VOLUME`](crate::audio::gain::MAX_VOLUME)
+    pub volume: f32,
+    pub muted: bool,
+    pub sink: SinkKind,
+    /// Device name as listed by [`output_devices`](crate::audio::sink::output_devices) for
+    /// [`SinkKind::Device`]; `None` uses the system default
+    pub device: Option<String>,
+    /// Output sample rate in Hz; `None` uses the device default
+    pub sample_rate: Option<u32>,
+    /// Device buffer size in frames; `None` lets the backend choose
+    pub buffer_size: Option<u32>,
+    /// Queue less audio ahead of the device, trading underrun safety for responsiveness
+    pub low_latency: bool,
+    pub channels: ChannelCount,
+    /// Matrix used when the source has more channels than the device
+    pub downmix: DownmixMode,
+    pub channel_map: ChannelMap,
+    /// Sample rate conversion engine and quality
+    pub resampler: ResamplerSettings,
+    /// Dither used when the output device takes 16-bit samples
+    pub dither: DitherMode,
+    pub loudness: LoudnessOptions,
+    /// Night mode compressor and equalizer, adjustable while playing
+    pub dsp: DspSettings,
+    /// Volumes in percent of the main and secondary tracks before they are mixed
+    pub main_volume: f32,
+    pub secondary_volume: f32,
+    /// Lower the main track while the secondary one is active
+    pub ducking: bool,
+}
+
+impl Default for AudioOptions {
+    fn default() -> Self {
+        Self {
+            filter: None,
+            delay: 0.0,
+            volume: 100.0,
+            muted: false,
+            sink: SinkKind::Device,
+            device: None,
+            sample_rate: None,
+            buffer_size: None,
+            low_latency: false,
+            channels: ChannelCount::Auto,
+            downmix: DownmixMode::Standard,
+            channel_map: ChannelMap::Normal,
+            resampler: ResamplerSettings::default(),
+            dither: DitherMode::default(),
+            loudness: LoudnessOptions::default(),
+            dsp: DspSettings::default(),
+            main_volume: 100.0,
+            secondary_volume: 100.0,
+            ducking: true,
+        }
+    }
+}
+
+enum SupervisorCommand {
+    SetDevice(Option<String>),
+    SetTrack(Option<AudioTrack>),
+    SetSecondaryTrack(Option<AudioTrack>),
+    Seek,
+    SetSpeed(f64),
+    Exit,
+}
+
 pub struct AudioHandle {
-    pub play_buf_thread: Option<thread::JoinHandle<()>>,
-    pub decode_thread: Option<thread::JoinHandle<()>>,
-    #[allow(unused)]
-    stream: cpal::Stream, // we need to keep the stream alive
-    shutdown: Arc<AtomicBool>,
+    supervisor: Option<thread::JoinHandle<()>>,
+    sx_supervisor: mpsc::Sender<SupervisorCommand>,
+    // f64 bits of the audio delay in seconds, read by the playback thread for every chunk
+    delay: Arc<AtomicU64>,
+    gain: Arc<GainControl>,
+    dsp: Arc<DspControl>,
+    mix: Arc<MixControl>,
+    counters: Arc<AudioCounters>,
+    tap: Arc<PcmTap>,
+}
+
+impl AudioHandle {
+    /// Buffering counters, accumulated across device changes.
+    pub fn stats(&self) -> AudioStats {
+        self.counters.snapshot()
+    }
+
+    /// Recent output audio for visualizations; copying starts once the tap is enabled.
+    pub fn pcm_tap(&self) -> Arc<PcmTap> {
+        self.tap.clone()
+    }
+
+    /// Sets the volume in percent; the callback ramps to it without clicks.
+    pub fn set_volume(&self, percent: f32) {
+        self.gain.set_volume(percent);
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.gain.set_muted(muted);
+    }
+
+    /// Fades out and holds the buffered audio, or fades back in on resume.
+    pub fn set_paused(&self, paused: bool) {
+        self.gain.set_paused(paused);
+    }
+
+    /// Restarts decoding at the current clock position, after the clock was moved.
+    pub fn seek(&self) {
+        let _ = self.sx_supervisor.send(SupervisorCommand::Seek);
+    }
+
+    /// Plays at `speed` times the normal rate without changing the pitch; the clock must be
+    /// set to the same speed.
+    pub fn set_speed(&self, speed: f64) {
+        let _ = self.sx_supervisor.send(SupervisorCommand::SetSpeed(speed));
+    }
+
+    /// Replaces the compressor and equalizer settings; the change is crossfaded in.
+    pub fn set_dsp(&self, settings: DspSettings) {
+        self.dsp.set(settings);
+    }
+
+    /// Shifts audio relative to the clock; positive values play audio later.
+    pub fn set_delay(&self, seconds: f64) {
+        self.delay.store(seconds.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Moves playback to another output device, continuing from the current clock position.
+    pub fn set_device(&self, name: Option<String>) {
+        let _ = self.sx_supervisor.send(SupervisorCommand::SetDevice(name));
+    }
+
+    /// Switches to another audio track, continuing from the current clock position.
+    pub fn set_track(&self, track: Option<AudioTrack>) {
+        let _ = self.sx_supervisor.send(SupervisorCommand::SetTrack(track));
+    }
+
+    /// Mixes another track, such as a commentary, over the main one; `None` stops it.
+    pub fn set_secondary_track(&self, track: Option<AudioTrack>) {
+        let _ = self
+            .sx_supervisor
+            .send(SupervisorCommand::SetSecondaryTrack(track));
+    }
+
+    /// Sets the main track volume in percent, applied before mixing and the master volume.
+    pub fn set_main_volume(&self, percent: f32) {
+        self.mix.set_main_volume(percent);
+    }
+
+    /// Sets the secondary track volume in percent, applied before mixing and the master volume.
+    pub fn set_secondary_volume(&self, percent: f32) {
+        self.mix.set_secondary_volume(percent);
+    }
+
+    /// Lowers the main track while the secondary one is active.
+    pub fn set_ducking(&self, enabled: bool) {
+        self.mix.set_ducking(enabled);
+    }
 }
 
 impl Drop for AudioHandle {
+    fn drop(&mut self) {
+        let _ = self.sx_supervisor.send(SupervisorCommand::Exit);
+        // ignore panics because panicking in Drop must be avoided
+        if let Some(handle) = self.supervisor.take() {
+            let _ = handle.join().ok();
+        }
+    }
+}
+
+/// Starts playing `track`, mixed with `secondary` if given, in sync with `clock`; without a
+/// track the output stays idle until one is set.
+pub fn spawn_audio(
+    track: Option<AudioTrack>,
+    secondary: Option<AudioTrack>,
+    clock: GlobalClock,
+    options: AudioOptions,
+) -> AudioHandle {
+    let (sx_supervisor, rx_supervisor) = mpsc::channel();
+    let delay = Arc::new(AtomicU64::new(options.delay.to_bits()));
+    let gain = Arc::new(GainControl::new(options.volume, options.muted));
+    let dsp = Arc::new(DspControl::new(options.dsp));
+    let mix = Arc::new(MixControl::new(
+        options.main_volume,
+        options.secondary_volume,
+        options.ducking,
+    ));
+    let counters = Arc::new(AudioCounters::new());
+    let tap = Arc::new(PcmTap::new());
+
+    // the sink is created, rebuilt and dropped on this thread only, since cpal streams
+    // are not Send on every platform
+    let mut supervisor = AudioSupervisor {
+        track,
+        secondary,
+        clock,
+        filter: options.filter,
+        sink: options.sink,
+        device: options.device,
+        sample_rate: options.sample_rate,
+        buffer_size: options.buffer_size,
+        low_latency: options.low_latency,
+        channels: options.channels,
+        downmix: options.downmix,
+        channel_map: options.channel_map,
+        resampler: options.resampler,
+        dither: options.dither,
+        loudness: options.loudness,
+        loudness_history: Arc::new(LoudnessHistory::new()),
+        speed: 1.0,
+        delay: delay.clone(),
+        gain: gain.clone(),
+        dsp: dsp.clone(),
+        mix: mix.clone(),
+        counters: counters.clone(),
+        tap: tap.clone(),
+        rx_commander: rx_supervisor,
+        last_error: None,
+    };
+    let supervisor = thread::spawn(move || supervisor.run());
+
+    AudioHandle {
+        supervisor: Some(supervisor),
+        sx_supervisor,
+        delay,
+        gain,
+        dsp,
+        mix,
+        counters,
+        tap,
+    }
+}
+
+/// Owns the current [`AudioOutput`] and replaces it when the device changes or disappears.
+struct AudioSupervisor {
+    track: Option<AudioTrack>,
+    secondary: Option<AudioTrack>,
+    clock: GlobalClock,
+    filter: Option<String>,
+    sink: SinkKind,
+    device: Option<String>,
+    sample_rate: Option<u32>,
+    buffer_size: Option<u32>,
+    low_latency: bool,
+    channels: ChannelCount,
+    downmix: DownmixMode,
+    channel_map: ChannelMap,
+    resampler: ResamplerSettings,
+    dither: DitherMode,
+    loudness: LoudnessOptions,
+    // measured by the normalizer for the current track, across seeks and output changes
+    loudness_history: Arc<LoudnessHistory>,
+    speed: f64,
+    delay: Arc<AtomicU64>,
+    gain: Arc<GainControl>,
+    dsp: Arc<DspControl>,
+    mix: Arc<MixControl>,
+    counters: Arc<AudioCounters>,
+    tap: Arc<PcmTap>,
+    rx_commander: mpsc::Receiver<SupervisorCommand>,
+    // last open failure, so a missing device is reported once rather than on every retry
+    last_error: Option<String>,
+}
+
+impl AudioSupervisor {
+    fn run(&mut self) {
+        let mut output: Option<AudioOutput> = None;
+        let mut next_open = Instant::now();
+
+        loop {
+            if output.as_ref().is_some_and(AudioOutput::is_lost) {
+                info!("audio device lost, reopening");
+                // join the old threads before the new output starts decoding
+                output = None;
+                next_open = Instant::now();
+            }
+
+            if output.is_none() && Instant::now() >= next_open {
+                match self.open() {
+                    Ok(opened) => {
+                        self.last_error = None;
+                        output = Some(opened);
+                    }
+                    Err(e) => {
+                        if self.last_error.as_ref() != Some(&e) {
+                            error!("failed to open audio output: {}", e);
+                            self.last_error = Some(e);
+                        }
+                        next_open = Instant::now() + REOPEN_INTERVAL;
+                    }
+                }
+            }
+
+            match self.rx_commander.recv_timeout(SUPERVISE_INTERVAL) {
+                Ok(SupervisorCommand::SetDevice(name)) => {
+                    self.device = name;
+                    // reopening a file sink would overwrite the recording
+                    if self.sink == SinkKind::Device {
+                        output = None;
+                        next_open = Instant::now();
+                    }
+                }
+                Ok(SupervisorCommand::SetTrack(track)) => {
+                    self.track = track;
+                    self.loudness_history.clear();
+                    self.switch_track(&mut output, &mut next_open);
+                }
+                Ok(SupervisorCommand::SetSecondaryTrack(track)) => {
+                    self.secondary = track;
+                    self.switch_track(&mut output, &mut next_open);
+                }
+                Ok(SupervisorCommand::Seek) => {
+                    // keep the device open and restart decoding at the clock position; without
+                    // an output, the next one opened starts there anyway
+                    if !self.restart(&mut output) {
+                        next_open = Instant::now();
+                    }
+                }
+                Ok(SupervisorCommand::SetSpeed(speed)) if !self.is_recording() => {
+                    // the tempo filter is set up when decoding starts
+                    self.speed = speed;
+                    output = None;
+                    next_open = Instant::now();
+                }
+                Ok(SupervisorCommand::SetSpeed(_)) => {
+                    warn!("the speed cannot be changed while recording to a file");
+                }
+                Ok(SupervisorCommand::Exit) | Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+            }
+        }
+    }
+
+    fn is_recording(&self) -> bool {
+        matches!(self.sink, SinkKind::Wav(_))
+    }
+
+    /// Restarts decoding on the open output at the clock position; returns false if the output
+    /// was dropped to be reopened instead.
+    fn restart(&self, output: &mut Option<AudioOutput>) -> bool {
+        let restarted = match (output.as_mut(), self.track.as_ref()) {
+            (Some(current), Some(track)) => current.seek(self, track),
+            (Some(_), None) => false,
+            (None, _) => return true,
+        };
+        if !restarted {
+            *output = None;
+        }
+        restarted
+    }
+
+    /// Plays the current tracks, reopening the output so a device can match their channels.
+    fn switch_track(&self, output: &mut Option<AudioOutput>, next_open: &mut Instant) {
+        // reopening a file sink would start the recording over, so it keeps its format
+        if !self.is_recording() || !self.restart(output) {
+            *output = None;
+            *next_open = Instant::now();
+        }
+    }
+
+    /// Opens the configured device and starts decoding at the current clock position.
+    fn open(&self) -> Result<AudioOutput, String> {
+        let track = self.track.as_ref().ok_or("no audio track")?;
+        let channels = match self.channels {
+            // stereo and mono sources play fine on whatever the device defaults to
+            ChannelCount::Auto => Some(track.channels).filter(|&c| c > 2).map(|c| c.min(8)),
+            ChannelCount::Fixed(count) => Some(count),
+        };
+        let request = SinkRequest {
+            sample_rate: self.sample_rate,
+            channels,
+            buffer_size: self
+                .buffer_size
+                .or(self.low_latency.then_some(LOW_LATENCY_BUFFER)),
+            dither: self.dither,
+        };
+        let sink = open_sink(&self.sink, self.device.as_deref(), request)?;
+
+        let description = sink.describe();
+        let output = AudioOutput::start(self, track, sink)?;
+        info!("playing audio track {} on {}", track, description);
+        if let Some(secondary) = &self.secondary {
+            info!("mixing in audio track {}", secondary);
+        }
+        Ok(output)
+    }
+}
+
+/// Sink for one device, fed from the current position by a [`Feed`].
+struct AudioOutput {
+    // stopped before the sink is dropped
+    feed: Option<Feed>,
+    sink: Box<dyn AudioSink>,
+    // seek serial the callback should play; audio queued before it is faded out and dropped
+    seek: Arc<AtomicU64>,
+    drift: Arc<DriftMonitor>,
+    target_sample_rate: u32,
+    target_channels: u16,
+}
+
+impl AudioOutput {
+    fn is_lost(&self) -> bool {
+        self.sink.is_lost()
+    }
+
+    fn start(
+        supervisor: &AudioSupervisor,
+        track: &AudioTrack,
+        mut sink: Box<dyn AudioSink>,
+    ) -> Result<Self, String> {
+        let target_sample_rate = sink.sample_rate();
+        let target_channels = sink.channels();
+        // fixed for the life of the output; changing it reopens the output
+        let speed = supervisor.speed;
+        let drift = Arc::new(DriftMonitor::new(speed));
+        let seek = Arc::new(AtomicU64::new(0));
+
+        // PLAYBACK & SINK
+        // room for the largest adaptive target plus a few decoded chunks
+        let samples_per_second = target_sample_rate as f64 * target_channels as f64;
+        let rb_capacity = (samples_per_second * (MAX_LATENCY + 0.25)) as usize;
+        let rb = RingBuffer::<f32>::new(rb_capacity.max(1024));
+        let (producer, consumer) = rb.split();
+        // (sample index, pts, seek serial) markers so the callback knows what it plays
+        let markers = RingBuffer::<(u64, f64, u64)>::new(1024);
+        let (marker_producer, marker_consumer) = markers.split();
+
+        let callback = OutputCallback {
+            consumer,
+            marker_consumer,
+            consumed: 0,
+            samples_per_second,
+            speed,
+            ramp: GainRamp::new(target_sample_rate, target_channels),
+            gain: supervisor.gain.clone(),
+            counters: supervisor.counters.clone(),
+            drift: drift.clone(),
+            seek: seek.clone(),
+            serial: 0,
+            starved: false,
+        };
+        sink.start(callback)?;
+
+        let end = FeedEnd {
+            producer,
+            marker_producer,
+            written: 0,
+            latency: AdaptiveLatency::new(supervisor.low_latency, &supervisor.counters),
+        };
+        let mut output = AudioOutput {
+            feed: None,
+            sink,
+            seek,
+            drift,
+            target_sample_rate,
+            target_channels,
+        };
+        output.feed = Some(Feed::start(supervisor, track, end, 0, &output));
+        Ok(output)
+    }
+
+    /// Restarts decoding at the current clock position without reopening the device; the
+    /// callback fades out what is still queued and fades the new position in. Returns false
+    /// if the output has to be reopened instead.
+    fn seek(&mut self, supervisor: &AudioSupervisor, track: &AudioTrack) -> bool {
+        let serial = self.seek.load(Ordering::Relaxed) + 1;
+        // start the fade while the old threads wind down
+        self.seek.store(serial, Ordering::Relaxed);
+        let Some(end) = self.feed.take().and_then(Feed::stop) else {
+            return false;
+        };
+        self.feed = Some(Feed::start(supervisor, track, end, serial, self));
+        true
+    }
+}
+
+/// Ring buffer ends and latency target handed from one feed to the next.
+struct FeedEnd {
+    producer: ringbuf::Producer<f32>,
+    marker_producer: ringbuf::Producer<(u64, f64, u64)>,
+    // total samples pushed into the ring buffer
+    written: u64,
+    // kept across seeks so the latency learned from underruns lasts as long as the device
+    latency: AdaptiveLatency,
+}
+
+/// Decode threads and playback thread filling the ring buffer from one position.
+struct Feed {
+    play_buf_thread: Option<thread::JoinHandle<FeedEnd>>,
+    decode_thread: Option<thread::JoinHandle<()>>,
+    secondary_thread: Option<thread::JoinHandle<()>>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl Feed {
+    /// Starts decoding at the current clock position into `end`, tagging audio with `serial`.
+    fn start(
+        supervisor: &AudioSupervisor,
+        track: &AudioTrack,
+        end: FeedEnd,
+        serial: u64,
+        output: &AudioOutput,
+    ) -> Self {
+        // use pre-resampled frames to match device sample rate and simplify playback
+        let (sx, rx) = flume::bounded::<(Vec<f32>, f64, u32, u16)>(100);
+
+        // shutdown flag used to signal threads to stop
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let target_sample_rate = output.target_sample_rate;
+        let target_channels = output.target_channels;
+        let clock = supervisor.clock.clone();
+        let drift = output.drift.clone();
+        let speed = supervisor.speed;
+
+        // the delay shifts audio later, so the sample heard now was decoded that much earlier
+        let delay = f64::from_bits(supervisor.delay.load(Ordering::Relaxed));
+        let start_of = |track: &AudioTrack| (clock.now() - delay - track.offset).max(0.0);
+        let setup = |sx, filter_spec, loudness| DecoderSetup {
+            target_sample_rate,
+            target_channels,
+            filter_spec,
+            downmix: supervisor.downmix,
+            channel_map: supervisor.channel_map.clone(),
+            resampler: supervisor.resampler,
+            speed,
+            loudness,
+            clock: clock.clone(),
+            delay: supervisor.delay.clone(),
+            drift: drift.clone(),
+            serial,
+            sx,
+            shutdown: shutdown.clone(),
+        };
+        let decode_thread = spawn_decoder(
+            track,
+            start_of(track),
+            setup(
+                sx,
+                supervisor.filter.clone(),
+                Some((supervisor.loudness, supervisor.loudness_history.clone())),
+            ),
+        );
+
+        // the secondary track skips the user filter and loudness, which are set up for the main
+        let (secondary_thread, secondary_mix) = match &supervisor.secondary {
+            Some(secondary) => {
+                let (sx, rx) = flume::bounded(100);
+                let secondary_setup = setup(sx, None, None);
+                let thread = spawn_decoder(secondary, start_of(secondary), secondary_setup);
+                let mix = SecondaryMix::new(
+                    rx,
+                    secondary.offset - track.offset,
+                    target_sample_rate,
+                    target_channels,
+                    speed,
+                );
+                (Some(thread), Some(mix))
+            }
+            None => (None, None),
+        };
+        let track_offset = track.offset;
+
+        let play_buf_thread = {
+            let shutdown = shutdown.clone();
+            let delay = supervisor.delay.clone();
+            let mut dsp =
+                DspStage::new(supervisor.dsp.clone(), target_sample_rate, target_channels);
+            let mut boost = VolumeBoost::new(target_sample_rate, target_channels);
+            let gain = supervisor.gain.clone();
+            let counters = supervisor.counters.clone();
+            let tap = supervisor.tap.clone();
+            let mix = supervisor.mix.clone();
+            let mut secondary_mix = secondary_mix;
+            let drift = drift.clone();
+            thread::spawn(move || {
+                let mut end = end;
+
+                // loop exits when sender is dropped by decode thread, making rx.recv return Err
+                loop {
+                    match rx.recv() {
+                        Ok((mut samples, chunk_pts, sample_rate, channels)) => {
+                            if sample_rate != target_sample_rate || channels != target_channels {
+                                error!(
+                                    "Warning: frame sample_rate/channels mismatch: {} {} vs target {} {}",
+                                    sample_rate, channels, target_sample_rate, target_channels
+                                );
+                            }
+
+                            // apply the delay here so waiting, dropping and the markers that
+                            // drive the audio clock all agree on the shifted timeline; the
+                            // boost limiter look-ahead delays what comes out a little too
+                            let pts = chunk_pts
+                                + f64::from_bits(delay.load(Ordering::Relaxed))
+                                + track_offset
+                                - boost.latency() * speed;
+
+                            // the latency is in real time, so it covers more media at speed
+                            let target_latency = end.latency.update(&counters) * speed;
+                            // the sink callback must not lock the clock, so it is synced here
+                            drift.sync_clock(&clock, serial);
+                            while pts > clock.now() + target_latency {
+                                // the clock stops while paused, so watch for shutdown too
+                                if shutdown.load(Ordering::Relaxed) {
+                                    return end;
+                                }
+                                thread::sleep(Duration::from_millis(4));
+                                drift.sync_clock(&clock, serial);
+                            }
+
+                            // unless audio drives the clock, small lateness is played and
+                            // resampled back into sync instead of leaving a gap
+                            let tolerance = if clock.mode() == ClockMode::Audio {
+                                0.0
+                            } else {
+                                MAX_COMPENSATED
+                            };
+                            if pts + target_latency + tolerance < clock.now() {
+                                counters.late_chunk();
+                                continue;
+                            }
+
+                            if let Some(secondary_mix) = secondary_mix.as_mut() {
+                                secondary_mix.mix_into(&mix, &mut samples, chunk_pts);
+                            }
+                            // here rather than in the decoder so changes are heard within the
+                            // ring buffer latency instead of after everything already decoded
+                            dsp.process(&mut samples);
+                            boost.process(&gain, &mut samples);
+                            tap.push(&samples, channels, sample_rate, pts);
+                            let _ = end.marker_producer.push((end.written, pts, serial));
+                            let pushed = end.producer.push_slice(&samples);
+                            if pushed < samples.len() {
+                                counters.overrun();
+                            }
+                            end.written += pushed as u64;
+                        }
+                        Err(_) => {
+                            // sender dropped -> exit loop
+                            break;
+                        }
+                    }
+                }
+                end
+            })
+        };
+
+        Feed {
+            play_buf_thread: Some(play_buf_thread),
+            decode_thread: Some(decode_thread),
+            secondary_thread,
+            shutdown,
+        }
+    }
+
+    /// Stops the threads and takes back the ring buffer ends; `None` if playback panicked.
+    fn stop(mut self) -> Option<FeedEnd> {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.decode_thread.take() {
+            let _ = handle.join().ok();
+        }
+        if let Some(handle) = self.secondary_thread.take() {
+            let _ = handle.join().ok();
+        }
+        self.play_buf_thread.take()?.join().ok()
+    }
+}
+
+/// State owned by the sink callback.
+pub struct OutputCallback {
+    consumer: ringbuf::Consumer<f32>,
+    marker_consumer: ringbuf::Consumer<(u64, f64, u64)>,
+    // total samples taken from the ring buffer, in the same index space as the markers
+    consumed: u64,
+    samples_per_second: f64,
+    // media seconds per second of output
+    speed: f64,
+    ramp: GainRamp,
+    gain: Arc<GainControl>,
+    counters: Arc<AudioCounters>,
+    // the clock takes locks, so the position heard is published through atomics instead
+    drift: Arc<DriftMonitor>,
+    // latest seek serial requested, and the serial of the audio being played
+    seek: Arc<AtomicU64>,
+    serial: u64,
+    // whether the previous buffer came up short, so one dry spell counts as one underrun
+    starved: bool,
+}
+
+impl OutputCallback {
+    /// Fills `data` with interleaved samples from the ring buffer and reports the time heard.
+    /// `latency` is how long until the first sample of `data` is heard, in seconds.
+    pub fn render(&mut self, data: &mut [f32], latency: f64) {
+        let seek = self.seek.load(Ordering::Relaxed);
+        if seek != self.serial && !self.flush(seek, data) {
+            return;
+        }
+        // hold buffered samples while paused so playback resumes where it stopped
+        if self.ramp.is_silent(&self.gain) {
+            data.fill(0.0);
+            return;
+        }
+
+        // skip to the marker of the chunk containing the next sample
+        let consumed = self.consumed;
+        while self
+            .marker_consumer
+            .iter()
+            .nth(1)
+            .is_some_and(|&(index, _, _)| index <= consumed)
+        {
+            self.marker_consumer.pop();
+        }
+        let first_pts = self
+            .marker_consumer
+            .iter()
+            .next()
+            .filter(|&&(index, _, _)| index <= consumed)
+            .map(|&(index, pts, _)| {
+                pts + (consumed - index) as f64 / self.samples_per_second * self.speed
+            });
+
+        let popped = self.consumer.pop_slice(data);
+        data[popped..].fill(0.0);
+        // running dry before anything played is just the start of playback
+        let starved = popped < data.len() && self.consumed > 0;
+        if starved && !self.starved {
+            self.counters.underrun();
+        }
+        self.starved = starved;
+        self.consumed += popped as u64;
+        self.counters
+            .set_buffered(self.consumer.len() as f64 / self.samples_per_second);
+        self.ramp.apply(&self.gain, data);
+
+        // the first sample of this buffer reaches the speaker after the output
+        // latency, so the sample audible now is that much earlier
+        if popped > 0
+            && let Some(pts) = first_pts
+        {
+            self.drift.report(pts - latency * self.speed, self.serial);
+        }
+    }
+
+    /// Fades out the audio queued before seek `serial`, then drops it. Returns true once the
+    /// new position is queued, leaving `data` to be filled from it and faded in.
+    fn flush(&mut self, serial: u64, data: &mut [f32]) -> bool {
+        // refilling after a seek is not an underrun
+        self.starved = true;
+        if !self.ramp.is_faded() {
+            let popped = self.consumer.pop_slice(data);
+            data[popped..].fill(0.0);
+            self.consumed += popped as u64;
+            self.ramp.fade_out(data);
+            return false;
+        }
+
+        // the feed pushes a marker before its samples, so count the samples first: without a
+        // marker of the new position, all of them are old
+        let queued = self.consumer.len() as u64;
+        let start = self
+            .marker_consumer
+            .iter()
+            .find(|&&(_, _, marker_serial)| marker_serial == serial)
+            .map(|&(index, _, _)| index);
+        let stale = start.map_or(queued, |index| index.saturating_sub(self.consumed));
+        self.consumed += self.consumer.discard(stale as usize) as u64;
+        while self
+            .marker_consumer
+            .iter()
+            .next()
+            .is_some_and(|&(_, _, marker_serial)| marker_serial != serial)
+        {
+            self.marker_consumer.pop();
+        }
+        if start.is_none() {
+            data.fill(0.0);
+            return false;
+        }
+        self.serial = serial;
+        true
+    }
+}
+
+impl Drop for Feed {
     fn drop(&mut self) {
         // signal threads to stop
         self.shutdown.store(true, Ordering::Relaxed);
@@ -32,187 +836,339 @@ impl Drop for AudioHandle {
             let _ = handle.join().ok();
         }
 
+        if let Some(handle) = self.secondary_thread.take() {
+            let _ = handle.join().ok();
+        }
+
         if let Some(handle) = self.play_buf_thread.take() {
             let _ = handle.join().ok();
         }
     }
 }
 
-pub fn spawn_audio(path: String, clock: GlobalClock) -> AudioHandle {
-    // use pre-resampled frames to match device sample rate and simplify playback
-    let (sx, rx) = flume::bounded::<(Vec<f32>, f64, u32, u16)>(100);
-
-    // shutdown flag used to signal threads to stop
-    let shutdown = Arc::new(AtomicBool::new(false));
-
-    // query device config once to determine target sample rate and channels for resampling
-    let host = cpal::default_host();
-    let device = host
-        .default_output_device()
-        .expect("no output device available");
-    let config = device
-        .default_output_config()
-        .expect("Failed to get default output config");
+/// Settings for a decode thread feeding one output.
+struct DecoderSetup {
+    target_sample_rate: u32,
+    target_channels: u16,
+    filter_spec: Option<String>,
+    downmix: DownmixMode,
+    channel_map: ChannelMap,
+    resampler: ResamplerSettings,
+    speed: f64,
+    // None skips ReplayGain and normalization
+    loudness: Option<(LoudnessOptions, Arc<LoudnessHistory>)>,
+    clock: GlobalClock,
+    delay: Arc<AtomicU64>,
+    drift: Arc<DriftMonitor>,
+    // seek serial the decoded audio is tagged with
+    serial: u64,
+    sx: flume::Sender<(Vec<f32>, f64, u32, u16)>,
+    shutdown: Arc<AtomicBool>,
+}
 
-    let (target_sample_rate, target_channels) = match config.sample_format() {
-        cpal::SampleFormat::F32 => {
-            let stream_config: cpal::StreamConfig = config.clone().into();
-            (
-                stream_config.sample_rate.0 as u32,
-                stream_config.channels as u16,
-            )
+/// Decodes `track` from `start` seconds on a new thread.
+fn spawn_decoder(track: &AudioTrack, start: f64, setup: DecoderSetup) -> thread::JoinHandle<()> {
+    let track = track.clone();
+    thread::spawn(move || {
+        let mut decoder = AudioDecoder::new(&track.path, track.stream_index)
+            .expect("Failed to open audio decoder");
+        // resume where the previous device stopped; late samples are dropped by playback
+        if start > 0.0
+            && let Err(e) = decoder.seek(start)
+        {
+            error!("failed to seek audio to {:.3}s: {:?}", start, e);
         }
-        other => panic!(
-            "Unsupported sample format for target detection: {:?}",
-            other
-        ),
-    };
+        let loudness = setup.loudness.and_then(|(loudness, history)| {
+            let tag_gain = replay_gain(&decoder, loudness.replay_gain, loudness.target);
+            if let Some(gain) = tag_gain {
+                info!("applying ReplayGain of {:+.2} dB", gain);
+            }
+            Loudness::new(
+                &loudness,
+                tag_gain,
+                history,
+                setup.target_sample_rate,
+                setup.target_channels,
+            )
+        });
+        let mut worker = AudioDecodeWorker {
+            time_base: decoder.time_base(),
+            target_sample_rate: setup.target_sample_rate,
+            target_channels: setup.target_channels,
+            filter_spec: setup.filter_spec,
+            filter: None,
+            filter_dirty: true,
+            resampler: None,
+            resampler_settings: setup.resampler,
+            soxr: setup.resampler.uses_soxr(),
+            speed: setup.speed,
+            tempo_anchor: None,
+            downmix: setup.downmix,
+            channel_map: setup.channel_map,
+            loudness,
+            clock: setup.clock,
+            delay: setup.delay,
+            track_offset: track.offset,
+            drift: DriftCompensator::new(setup.drift, setup.serial),
+            compensating: false,
+            sx: setup.sx,
+            shutdown: setup.shutdown,
+        };
+        worker.run(decoder);
+    })
+}
 
-    let decode_path = path.clone();
-    let sx2 = sx.clone();
-    let decode_shutdown = shutdown.clone(); // clone for decode thread
-    let decode_thread = thread::spawn(move || {
-        let decoder = AudioDecoder::new(&decode_path).expect("Failed to open audio decoder");
-        let time_base = decoder.time_base();
-        let mut resampler: Option<ffmpeg::software::resampling::Context> = None;
+/// State owned by the audio decode thread.
+struct AudioDecodeWorker {
+    time_base: ffmpeg::Rational,
+    target_sample_rate: u32,
+    target_channels: u16,
+    filter_spec: Option<String>,
+    filter: Option<AudioFilter>,
+    // set when the filter graph must be rebuilt before the next frame
+    filter_dirty: bool,
+    resampler: Option<ffmpeg::software::resampling::Context>,
+    resampler_settings: ResamplerSettings,
+    // cleared when FFmpeg lacks libsoxr, falling back to long swresample filters
+    soxr: bool,
+    speed: f64,
+    // pts where the tempo filter started, which its output timestamps count from
+    tempo_anchor: Option<f64>,
+    downmix: DownmixMode,
+    channel_map: ChannelMap,
+    loudness: Option<Loudness>,
+    clock: GlobalClock,
+    delay: Arc<AtomicU64>,
+    // per-track delay added to the user delay, e.g. for an external dub
+    track_offset: f64,
+    drift: DriftCompensator,
+    // whether the resampler currently has a compensation set that must be cleared
+    compensating: bool,
+    sx: flume::Sender<(Vec<f32>, f64, u32, u16)>,
+    shutdown: Arc<AtomicBool>,
+}
 
+impl AudioDecodeWorker {
+    fn run(&mut self, decoder: AudioDecoder) {
         for frame in decoder {
             // check shutdown flag each iteration to allow timely exit of decode thread
-            if decode_shutdown.load(Ordering::Relaxed) {
-                break;
+            if self.shutdown.load(Ordering::Relaxed) {
+                return;
             }
 
-            let pts_seconds = frame.pts().unwrap() as f64 * time_base.numerator() as f64
-                / time_base.denominator() as f64;
+            if self.filter_dirty {
+                self.filter_dirty = false;
+                self.filter = self.build_filter(&frame);
+                self.tempo_anchor = None;
+            }
 
-            if resampler.is_none() {
-                let in_format = frame.format();
-                let in_layout = frame.channel_layout();
-                let in_rate = frame.rate();
+            let Some(filter) = self.filter.as_mut() else {
+                if !self.resample(&frame, self.time_base) {
+                    return;
+                }
+                continue;
+            };
 
-                let out_format = ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Packed);
-                let out_layout = match target_channels {
-                    1 => ffmpeg::channel_layout::ChannelLayout::MONO,
-                    2 => ffmpeg::channel_layout::ChannelLayout::STEREO,
-                    _ => ffmpeg::channel_layout::ChannelLayout::STEREO_DOWNMIX,
-                };
-                let out_rate = target_sample_rate;
+            if let Err(e) = filter.push(&frame) {
+                error!("failed to feed audio filter: {:?}", e);
+                self.filter = None;
+                self.tempo_anchor = None;
+                continue;
+            }
+            if self.tempo_anchor.is_none() && tempo_spec(self.speed).is_some() {
+                let time_base: f64 = self.time_base.into();
+                self.tempo_anchor = frame.pts().map(|pts| pts as f64 * time_base);
+            }
+            let time_base = filter.time_base();
+            let filtered: Vec<_> = std::iter::from_fn(|| filter.pull()).collect();
+            for frame in filtered {
+                if !self.resample(&frame, time_base) {
+                    return;
+                }
+            }
+        }
 
-                resampler = Some(ffmpeg::software::resampling::Context::get(
-                    in_format, in_layout, in_rate, out_format, out_layout, out_rate,
-                )
-                .expect("Failed to create resampler"));
+        // drain samples still held by the filter, e.g. loudnorm lookahead
+        if let Some(mut filter) = self.filter.take()
+            && filter.flush().is_ok()
+        {
+            let time_base = filter.time_base();
+            while let Some(frame) = filter.pull() {
+                if !self.resample(&frame, time_base) {
+                    return;
+                }
             }
+        }
+    }
 
-            if let Some(ref mut r) = resampler {
-                let mut resampled = ffmpeg::util::frame::Audio::empty();
-                match r.run(&frame, &mut resampled) {
-                    Ok(_) => {
-                        let data = resampled.data(0);
-                        let mut samples: Vec<f32> = Vec::new();
-                        if !data.is_empty() {
-                            samples.reserve(data.len() / 4);
-                            for chunk in data.chunks_exact(4) {
-                                samples.push(f32::from_ne_bytes([
-                                    chunk[0], chunk[1], chunk[2], chunk[3],
-                                ]));
-                            }
-                        }
+    fn build_filter(&mut self, frame: &AudioFrame) -> Option<AudioFilter> {
+        let spec = self
+            .filter_spec
+            .clone()
+            .into_iter()
+            .chain(tempo_spec(self.speed))
+            .collect::<Vec<_>>()
+            .join(",");
+        if spec.is_empty() {
+            return None;
+        }
+        match AudioFilter::new(&spec, frame, self.time_base) {
+            Ok(filter) => Some(filter),
+            Err(e) => {
+                // keep playing unfiltered rather than stopping audio on a bad graph
+                error!("failed to create audio filter '{}': {:?}", spec, e);
+                None
+            }
+        }
+    }
 
-                        let sample_rate = resampled.rate();
-                        let channels = resampled.channels();
+    /// Resamples a frame and sends it to the playback thread; returns false if the thread should exit.
+    fn resample(&mut self, frame: &AudioFrame, time_base: ffmpeg::Rational) -> bool {
+        let mut pts_seconds = frame.pts().unwrap() as f64 * time_base.numerator() as f64
+            / time_base.denominator() as f64;
+        // the tempo filter stamps its output in real time from where it started
+        if let Some(anchor) = self.tempo_anchor {
+            pts_seconds = anchor + (pts_seconds - anchor) * self.speed;
+        }
 
-                        if sx2
-                            .send((samples, pts_seconds, sample_rate, channels))
-                            .is_err()
-                        {
-                            // stop because playback thread likely terminated (channel closed)
-                            break;
+        // filters may change the sample format, layout or rate, so recreate the resampler to match
+        let stale = self.resampler.as_ref().is_none_or(|r| {
+            let input = r.input();
+            input.format != frame.format()
+                || input.channel_layout != frame.channel_layout()
+                || input.rate != frame.rate()
+        });
+        if stale {
+            let out_format = ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Packed);
+            // FFmpeg's default layout for the count, e.g. 5.1 for six channels and 7.1 for eight
+            let out_layout =
+                ffmpeg::channel_layout::ChannelLayout::default(self.target_channels as i32);
+
+            let create = |settings: ResamplerSettings, soxr: bool| {
+                let mut options = self.downmix.resampler_options();
+                settings.apply(&mut options, soxr);
+                ffmpeg::software::resampling::Context::get_with(
+                    frame.format(),
+                    frame.channel_layout(),
+                    frame.rate(),
+                    out_format,
+                    out_layout,
+                    self.target_sample_rate,
+                    options,
+                )
+            };
+            let resampler = match create(self.resampler_settings, self.soxr) {
+                Err(e) if self.soxr => {
+                    warn!("soxr resampler unavailable ({:?}), using swresample", e);
+                    self.soxr = false;
+                    create(self.resampler_settings, false)
+                }
+                result => result,
+            };
+            let resampler = match resampler {
+                Ok(resampler) => resampler,
+                Err(e) => {
+                    error!(
+                        "failed to create the resampler with the requested settings ({:?}), \
+                         using the defaults",
+                        e
+                    );
+                    // do not retry the failing settings on the next format change
+                    self.resampler_settings = ResamplerSettings::default();
+                    match create(self.resampler_settings, false) {
+                        Ok(resampler) => resampler,
+                        Err(e) => {
+                            error!("failed to create resampler: {:?}", e);
+                            return false;
                         }
                     }
-                    Err(e) => {
-                        error!("Resampling error: {:?}", e);
-                        break;
-                    }
                 }
-            }
+            };
+            self.resampler = Some(resampler);
+            self.compensating = false;
         }
-    });
-
-    // PLAYBACK & CPAL STREAM
-    let rb_capacity = (target_sample_rate as usize) * (target_channels as usize) * 2;
-    let rb = RingBuffer::<f32>::new(rb_capacity.max(1024));
-    let (producer, mut consumer) = rb.split();
-
-    match config.sample_format() {
-        cpal::SampleFormat::F32 => {
-            let stream_config: cpal::StreamConfig = config.into();
-            let err_fn = |err| error!("an error occurred on stream: {}", err);
-            let stream = device
-                .build_output_stream(
-                    &stream_config,
-                    move |data: &mut [f32], _| {
-                        for sample in data.iter_mut() {
-                            if let Some(s) = consumer.pop() {
-                                *sample = s;
-                            } else {
-                                *sample = 0.0;
-                            }
-                        }
-                    },
-                    err_fn,
-                    None::<Duration>,
-                )
-                .expect("Failed to build output stream");
-            stream.play().expect("Failed to play stream");
-
-            let play_buf_thread = {
-                let rx = rx.clone();
-                thread::spawn(move || {
-                    let target_latency = 0.1_f64;
-                    let mut prod = producer;
-
-                    // loop exits when sender is dropped by decode thread, making rx.recv return Err
-                    loop {
-                        match rx.recv() {
-                            Ok((samples, pts, sample_rate, channels)) => {
-                                if sample_rate != target_sample_rate || channels != target_channels
-                                {
-                                    error!(
-                                        "Warning: frame sample_rate/channels mismatch: {} {} vs target {} {}",
-                                        sample_rate, channels, target_sample_rate, target_channels
-                                    );
-                                }
+        self.compensate(frame, pts_seconds);
 
-                                while pts > clock.now() + target_latency {
-                                    thread::sleep(Duration::from_millis(4));
-                                }
+        let mut resampled = ffmpeg::util::frame::Audio::empty();
+        match self.resampler.as_mut().unwrap().run(frame, &mut resampled) {
+            Ok(_) => {
+                let data = resampled.data(0);
+                let mut samples: Vec<f32> = Vec::new();
+                if !data.is_empty() {
+                    samples.reserve(data.len() / 4);
+                    for chunk in data.chunks_exact(4) {
+                        samples.push(f32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+                    }
+                }
 
-                                if pts + target_latency < clock.now() {
-                                    continue;
-                                }
+                let sample_rate = resampled.rate();
+                let channels = resampled.channels();
+                self.channel_map.apply(&mut samples, channels as usize);
+                if let Some(loudness) = self.loudness.as_mut() {
+                    loudness.process(&mut samples);
+                    // the limiter look-ahead delays what comes out by a few milliseconds
+                    pts_seconds -= loudness.latency() * self.speed;
+                }
 
-                                for s in samples {
-                                    let _ = prod.push(s);
-                                }
-                            }
-                            Err(_) => {
-                                // sender dropped -> exit loop
-                                break;
-                            }
-                        }
-                    }
-                })
-            };
+                // stop if the playback thread terminated (channel closed)
+                self.sx
+                    .send((samples, pts_seconds, sample_rate, channels))
+                    .is_ok()
+            }
+            Err(e) => {
+                error!("Resampling error: {:?}", e);
+                false
+            }
+        }
+    }
 
-            AudioHandle {
-                play_buf_thread: Some(play_buf_thread),
-                decode_thread: Some(decode_thread),
-                stream,
-                // transfer ownership of the shutdown flag to the handle so it can signal threads
-                shutdown,
+    /// Stretches or squeezes the next frame so the audio heard follows a video or external clock.
+    fn compensate(&mut self, frame: &AudioFrame, pts_seconds: f64) {
+        let out_samples =
+            frame.samples() as i64 * self.target_sample_rate as i64 / frame.rate().max(1) as i64;
+        // corrections are measured in media time, which runs `speed` times faster
+        let duration = out_samples as f64 / self.target_sample_rate as f64 * self.speed;
+        let correction = if self.clock.mode() == ClockMode::Audio {
+            // the clock follows the audio, so there is nothing to correct
+            self.drift.reset();
+            0.0
+        } else {
+            let delay = f64::from_bits(self.delay.load(Ordering::Relaxed)) + self.track_offset;
+            self.drift
+                .correction(pts_seconds + delay, duration, &self.clock)
+        };
+        let delta = (correction / self.speed * self.target_sample_rate as f64).round() as i32;
+        if delta == 0 && !self.compensating {
+            return;
+        }
+        self.compensating = delta != 0;
+        if self.soxr {
+            // soxr has no compensation and rejects every request with EINVAL
+            if !SOXR_COMPENSATION_WARNED.swap(true, Ordering::Relaxed) {
+                warn!("the soxr resampler cannot compensate drift, audio may drift from video");
             }
+            return;
+        }
+
+        let resampler = self.resampler.as_mut().unwrap();
+        // the distance is in output samples; a zero delta switches compensation off
+        // SAFETY: `resample` initializes the resampler before compensating, and it is owned by
+        // `self`, so the context pointer is valid and not used concurrently. The distance is at
+        // least one sample and the drift compensator keeps the delta to a few percent of it, so
+        // both are in the range swresample accepts.
+        let result = unsafe {
+            ffmpeg::sys::swr_set_compensation(
+                resampler.as_mut_ptr(),
+                delta,
+                out_samples.max(1) as i32,
+            )
+        };
+        if result < 0 {
+            error!(
+                "failed to set audio drift compensation: {:?}",
+                ffmpeg::Error::from(result)
+            );
+            self.compensating = false;
         }
-        other => panic!("Unsupported sample format: {:?}", other),
     }
 }
@@ -0,0 +1,117 @@
+use ffmpeg_next as ffmpeg;
+
+/// Sample rate conversion quality, used when the device rate differs from the file rate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ResampleQuality {
+    /// Short filters, for slow machines
+    Fast,
+    /// libswresample defaults
+    #[default]
+    Default,
+    /// The SoX resampler at very high precision, or long swresample filters when FFmpeg was
+    /// built without libsoxr
+    High,
+}
+
+/// Resampler engine settings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResamplerSettings {
+    pub quality: ResampleQuality,
+    /// Taps of the swresample interpolation filter, overriding the quality preset
+    pub filter_length: Option<u32>,
+}
+
+impl ResamplerSettings {
+    /// Whether these settings ask for the soxr engine.
+    pub fn uses_soxr(&self) -> bool {
+        self.quality == ResampleQuality::High
+    }
+
+    /// Adds the libswresample options for these settings to `options`; with `soxr` false the
+    /// soxr engine is replaced by an swresample setup of similar quality.
+    pub fn apply(&self, options: &mut ffmpeg::Dictionary, soxr: bool) {
+        match self.quality {
+            ResampleQuality::Fast => {
+                options.set("filter_size", "8");
+                options.set("phase_shift", "6");
+                options.set("linear_interp", "1");
+            }
+            ResampleQuality::Default => {}
+            ResampleQuality::High if soxr => {
+                options.set("resampler", "soxr");
+                // bits of precision; 28 is soxr's very high quality recipe
+                options.set("precision", "28");
+            }
+            ResampleQuality::High => {
+                options.set("filter_size", "64");
+                options.set("phase_shift", "14");
+                options.set("linear_interp", "1");
+            }
+        }
+        if let Some(length) = self.filter_length {
+            options.set("filter_size", &length.to_string());
+        }
+    }
+}
+
+/// Noise added when the f32 mix is converted for a 16-bit device, hiding truncation
+/// distortion in quiet passages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DitherMode {
+    /// Plain rounding
+    None,
+    /// Uniform noise of one step
+    Rectangular,
+    /// Triangular (TPDF) noise, the usual choice
+    #[default]
+    Triangular,
+    /// Triangular noise shifted towards high frequencies, where it is less audible
+    TriangularHp,
+}
+
+/// Per-stream dither state.
+pub struct Dither {
+    mode: DitherMode,
+    // one quantization step of the output format
+    step: f32,
+    seed: u32,
+    // previous uniform value per channel, for the high-passed variant
+    previous: Vec<f32>,
+    channel: usize,
+}
+
+impl Dither {
+    /// Dither for an integer output of `bits` bits with `channels` interleaved channels.
+    pub fn new(mode: DitherMode, bits: u32, channels: u16) -> Self {
+        Self {
+            mode,
+            step: 2.0 / (1u64 << bits) as f32,
+            seed: 0x9e37_79b9,
+            previous: vec![0.0; channels.max(1) as usize],
+            channel: 0,
+        }
+    }
+
+    /// A uniform value in [-0.5, 0.5) from a xorshift generator; quality is irrelevant here.
+    fn uniform(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed as f32 / 4_294_967_296.0 - 0.5
+    }
+
+    pub fn apply(&mut self, sample: f32) -> f32 {
+        let noise = match self.mode {
+            DitherMode::None => return sample,
+            DitherMode::Rectangular => self.uniform(),
+            DitherMode::Triangular => self.uniform() + self.uniform(),
+            DitherMode::TriangularHp => {
+                let value = self.uniform();
+                let previous = std::mem::replace(&mut self.previous[self.channel], value);
+                self.channel = (self.channel + 1) % self.previous.len();
+                value - previous
+            }
+        };
+        sample + noise * self.step
+    }
+}
@@ -0,0 +1,588 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use tracing::{error, warn};
+
+use crate::audio::{
+    player::OutputCallback,
+    resample::{Dither, DitherMode},
+};
+
+// rate and channels of headless sinks when nothing else is requested
+const HEADLESS_SAMPLE_RATE: u32 = 48000;
+const HEADLESS_CHANNELS: u16 = 2;
+// headless sinks pull audio in periods of this length
+const PUMP_PERIOD_SECONDS: f64 = 0.01;
+
+/// Where mixed audio goes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum SinkKind {
+    /// A sound device through cpal
+    #[default]
+    Device,
+    /// Discard audio at the real-time rate, for machines without sound hardware
+    Null,
+    /// Record exactly what would have been played to a 32-bit float WAV file
+    Wav(PathBuf),
+}
+
+impl FromStr for SinkKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "device" => Ok(SinkKind::Device),
+            "null" => Ok(SinkKind::Null),
+            _ => match s.strip_prefix("wav:") {
+                Some(path) if !path.is_empty() => Ok(SinkKind::Wav(path.into())),
+                _ => Err(format!("expected device, null or wav:<path>, got '{}'", s)),
+            },
+        }
+    }
+}
+
+/// Format requested from a sink; `None` leaves the choice to the sink.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SinkRequest {
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    /// Buffer size in frames, for sinks with a device buffer
+    pub buffer_size: Option<u32>,
+    /// Dither used when the device takes 16-bit samples
+    pub dither: DitherMode,
+}
+
+/// Destination for the mixed output, pulling interleaved f32 samples from an [`OutputCallback`].
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+
+    fn channels(&self) -> u16;
+
+    /// Human readable description for logs.
+    fn describe(&self) -> String;
+
+    /// Starts pulling audio. Called once; dropping the sink stops it.
+    fn start(&mut self, callback: OutputCallback) -> Result<(), String>;
+
+    /// True once the sink can no longer play, e.g. because the device was unplugged.
+    fn is_lost(&self) -> bool {
+        false
+    }
+}
+
+/// Opens the sink selected by `kind`; `device` names the cpal device for [`SinkKind::Device`].
+pub fn open_sink(
+    kind: &SinkKind,
+    device: Option<&str>,
+    request: SinkRequest,
+) -> Result<Box<dyn AudioSink>, String> {
+    Ok(match kind {
+        SinkKind::Device => Box::new(CpalSink::open(device, request)?),
+        SinkKind::Null => Box::new(NullSink::new(request)),
+        SinkKind::Wav(path) => Box::new(WavSink::create(path, request)?),
+    })
+}
+
+/// Names of the output devices of the default host, for `--audio-device`.
+pub fn output_devices() -> Vec<String> {
+    let host = cpal::default_host();
+    match host.output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            error!("failed to enumerate audio devices: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Looks up an output device by name, falling back to the default device.
+fn find_device(host: &cpal::Host, name: Option<&str>) -> Option<cpal::Device> {
+    if let Some(name) = name {
+        let found = host
+            .output_devices()
+            .ok()
+            .and_then(|mut devices| devices.find(|device| device.name().is_ok_and(|n| n == name)));
+        if found.is_some() {
+            return found;
+        }
+        warn!(
+            "audio device '{}' not found, using the default device",
+            name
+        );
+    }
+    host.default_output_device()
+}
+
+/// The device default configuration, moved to `sample_rate` and `channels` when requested
+/// and supported.
+fn choose_config(
+    device: &cpal::Device,
+    sample_rate: Option<u32>,
+    channels: Option<u16>,
+) -> Result<cpal::SupportedStreamConfig, String> {
+    let default = device
+        .default_output_config()
+        .map_err(|e| format!("failed to get output config: {}", e))?;
+    if sample_rate.is_none() && channels.is_none_or(|c| c == default.channels()) {
+        return Ok(default);
+    }
+    let rate = cpal::SampleRate(sample_rate.unwrap_or(default.sample_rate().0));
+    let channels = channels.unwrap_or(default.channels());
+    let configs = device
+        .supported_output_configs()
+        .map_err(|e| format!("failed to query output configs: {}", e))?;
+    // prefer the requested channel count, then the default format, at the requested rate
+    let chosen = configs
+        .filter_map(|range| {
+            let score = 2 * u8::from(range.channels() != channels)
+                + u8::from(range.sample_format() != default.sample_format());
+            range
+                .try_with_sample_rate(rate)
+                .map(|config| (score, config))
+        })
+        .min_by_key(|(score, _)| *score);
+    match chosen {
+        Some((_, config)) => {
+            if config.channels() != channels {
+                warn!(
+                    "{} channels are not supported by the device, using {}",
+                    channels,
+                    config.channels()
+                );
+            }
+            Ok(config)
+        }
+        None => {
+            warn!(
+                "sample rate {} Hz is not supported by the device, using {} Hz",
+                rate.0,
+                default.sample_rate().0
+            );
+            Ok(default)
+        }
+    }
+}
+
+/// A fixed buffer size of `frames`, clamped to what the device supports.
+fn choose_buffer_size(
+    config: &cpal::SupportedStreamConfig,
+    frames: Option<u32>,
+) -> cpal::BufferSize {
+    let Some(frames) = frames else {
+        return cpal::BufferSize::Default;
+    };
+    match *config.buffer_size() {
+        cpal::SupportedBufferSize::Range { min, max } if !(min..=max).contains(&frames) => {
+            let clamped = frames.clamp(min, max);
+            warn!(
+                "buffer size {} is outside the device range {}..={}, using {}",
+                frames, min, max, clamped
+            );
+            cpal::BufferSize::Fixed(clamped)
+        }
+        _ => cpal::BufferSize::Fixed(frames),
+    }
+}
+
+/// Output through a cpal device.
+pub struct CpalSink {
+    device: cpal::Device,
+    name: String,
+    config: cpal::StreamConfig,
+    sample_format: cpal::SampleFormat,
+    dither: DitherMode,
+    #[allow(unused)]
+    stream: Option<cpal::Stream>, // we need to keep the stream alive
+    // set by the stream error callback, e.g. when the device is unplugged
+    lost: Arc<AtomicBool>,
+}
+
+impl CpalSink {
+    pub fn open(name: Option<&str>, request: SinkRequest) -> Result<Self, String> {
+        let host = cpal::default_host();
+        let device = find_device(&host, name).ok_or("no output device available")?;
+        let name = device.name().unwrap_or_else(|_| "unknown".to_string());
+        let supported = choose_config(&device, request.sample_rate, request.channels)
+            .map_err(|e| format!("{}: {}", name, e))?;
+        let mut config = supported.config();
+        config.buffer_size = choose_buffer_size(&supported, request.buffer_size);
+        Ok(Self {
+            device,
+            name,
+            config,
+            sample_format: supported.sample_format(),
+            dither: request.dither,
+            stream: None,
+            lost: Arc::new(AtomicBool::new(false)),
+        })
+    }
+}
+
+impl AudioSink for CpalSink {
+    fn sample_rate(&self) -> u32 {
+        self.config.sample_rate.0
+    }
+
+    fn channels(&self) -> u16 {
+        self.config.channels
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "'{}' ({} Hz, {} channels, {:?})",
+            self.name, self.config.sample_rate.0, self.config.channels, self.sample_format
+        )
+    }
+
+    fn start(&mut self, mut callback: OutputCallback) -> Result<(), String> {
+        let lost = self.lost.clone();
+        let err_fn = move |err: cpal::StreamError| match err {
+            // the stream is dead; the supervisor reopens it at the clock position
+            cpal::StreamError::DeviceNotAvailable => {
+                warn!("audio device is no longer available");
+                lost.store(true, Ordering::Relaxed);
+            }
+            // transient backend errors, such as an xrun, do not stop the stream
+            err => error!("an error occurred on stream: {}", err),
+        };
+        let device = &self.device;
+        let config = &self.config;
+        // 32-bit and float formats have far more resolution than the mix needs
+        let no_dither = Dither::new(DitherMode::None, 32, config.channels);
+        let dither = Dither::new(self.dither, 16, config.channels);
+        // mixing stays in f32; other device formats are converted at the end of the callback
+        let stream = match self.sample_format {
+            cpal::SampleFormat::F32 => device.build_output_stream(
+                config,
+                move |data: &mut [f32], info: &cpal::OutputCallbackInfo| {
+                    callback.render(data, output_latency(info))
+                },
+                err_fn,
+                None::<Duration>,
+            ),
+            cpal::SampleFormat::F64 => {
+                build_stream::<f64>(device, config, callback, no_dither, err_fn)
+            }
+            cpal::SampleFormat::I32 => {
+                build_stream::<i32>(device, config, callback, no_dither, err_fn)
+            }
+            cpal::SampleFormat::I16 => {
+                build_stream::<i16>(device, config, callback, dither, err_fn)
+            }
+            cpal::SampleFormat::U16 => {
+                build_stream::<u16>(device, config, callback, dither, err_fn)
+            }
+            other => return Err(format!("unsupported sample format {:?}", other)),
+        }
+        .map_err(|e| format!("failed to build output stream: {}", e))?;
+        stream
+            .play()
+            .map_err(|e| format!("failed to play stream: {}", e))?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
+    }
+}
+
+/// The first sample of a callback buffer reaches the speaker this many seconds later.
+fn output_latency(info: &cpal::OutputCallbackInfo) -> f64 {
+    let timestamp = info.timestamp();
+    timestamp
+        .playback
+        .duration_since(&timestamp.callback)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Builds a stream for a non-f32 device format, converting from the f32 mix.
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut callback: OutputCallback,
+    mut dither: Dither,
+    err_fn: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample + cpal::FromSample<f32>,
+{
+    let mut mix: Vec<f32> = Vec::new();
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], info: &cpal::OutputCallbackInfo| {
+            // grows to the largest buffer the device asks for, then stays allocated
+            if mix.len() < data.len() {
+                mix.resize(data.len(), 0.0);
+            }
+            let mix = &mut mix[..data.len()];
+            callback.render(mix, output_latency(info));
+            for (out, &sample) in data.iter_mut().zip(mix.iter()) {
+                *out = T::from_sample(dither.apply(sample));
+            }
+        },
+        err_fn,
+        None::<Duration>,
+    )
+}
+
+/// Thread that pulls audio in fixed periods paced by the wall clock, standing in for a
+/// device callback.
+struct Pump {
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Pump {
+    fn spawn(
+        sample_rate: u32,
+        channels: u16,
+        mut callback: OutputCallback,
+        mut write: impl FnMut(&[f32]) + Send + 'static,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::spawn(move || {
+            let frames = ((sample_rate as f64 * PUMP_PERIOD_SECONDS) as usize).max(1);
+            let period = Duration::from_secs_f64(frames as f64 / sample_rate as f64);
+            let mut buffer = vec![0.0; frames * channels as usize];
+            // absolute deadlines, so sleep overshoot does not accumulate into drift
+            let mut deadline = Instant::now();
+            while !thread_stop.load(Ordering::Relaxed) {
+                callback.render(&mut buffer, 0.0);
+                write(&buffer);
+                deadline += period;
+                if let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                    thread::sleep(wait);
+                }
+            }
+        });
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Pump {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.thread.take() {
+            let _ = handle.join().ok();
+        }
+    }
+}
+
+/// Plays into nothing at the real-time rate, so the audio clock keeps working without
+/// sound hardware.
+pub struct NullSink {
+    sample_rate: u32,
+    channels: u16,
+    pump: Option<Pump>,
+}
+
+impl NullSink {
+    pub fn new(request: SinkRequest) -> Self {
+        Self {
+            sample_rate: request.sample_rate.unwrap_or(HEADLESS_SAMPLE_RATE),
+            channels: request.channels.unwrap_or(HEADLESS_CHANNELS),
+            pump: None,
+        }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn describe(&self) -> String {
+        format!("null ({} Hz, {} channels)", self.sample_rate, self.channels)
+    }
+
+    fn start(&mut self, callback: OutputCallback) -> Result<(), String> {
+        self.pump = Some(Pump::spawn(
+            self.sample_rate,
+            self.channels,
+            callback,
+            |_| {},
+        ));
+        Ok(())
+    }
+}
+
+/// Records the output to a WAV file at the real-time rate.
+pub struct WavSink {
+    path: PathBuf,
+    sample_rate: u32,
+    channels: u16,
+    writer: Option<WavWriter>,
+    pump: Option<Pump>,
+}
+
+impl WavSink {
+    pub fn create(path: &Path, request: SinkRequest) -> Result<Self, String> {
+        let sample_rate = request.sample_rate.unwrap_or(HEADLESS_SAMPLE_RATE);
+        let channels = request.channels.unwrap_or(HEADLESS_CHANNELS);
+        let writer = WavWriter::create(path, sample_rate, channels)
+            .map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            sample_rate,
+            channels,
+            writer: Some(writer),
+            pump: None,
+        })
+    }
+}
+
+impl AudioSink for WavSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "{} ({} Hz, {} channels)",
+            self.path.display(),
+            self.sample_rate,
+            self.channels
+        )
+    }
+
+    fn start(&mut self, callback: OutputCallback) -> Result<(), String> {
+        let mut writer = self.writer.take().ok_or("sink already started")?;
+        self.pump = Some(Pump::spawn(
+            self.sample_rate,
+            self.channels,
+            callback,
+            move |samples| {
+                if let Err(e) = writer.write(samples) {
+                    error!("failed to write audio: {}", e);
+                }
+            },
+        ));
+        Ok(())
+    }
+}
+
+// offsets of the size fields patched when the file is finished
+const RIFF_SIZE_OFFSET: u64 = 4;
+const DATA_SIZE_OFFSET: u64 = 42;
+
+/// Minimal writer for interleaved 32-bit float WAV files.
+struct WavWriter {
+    file: BufWriter<File>,
+    data_bytes: u32,
+}
+
+impl WavWriter {
+    fn create(path: &Path, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let block_align = channels * 4;
+        file.write_all(b"RIFF")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        file.write_all(b"WAVE")?;
+        file.write_all(b"fmt ")?;
+        file.write_all(&18u32.to_le_bytes())?;
+        file.write_all(&3u16.to_le_bytes())?; // WAVE_FORMAT_IEEE_FLOAT
+        file.write_all(&channels.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&32u16.to_le_bytes())?;
+        file.write_all(&0u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            file,
+            data_bytes: 0,
+        })
+    }
+
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples {
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+        // sizes are 32-bit; past 4 GiB keep writing and leave the header saturated
+        self.data_bytes = self.data_bytes.saturating_add(samples.len() as u32 * 4);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let riff_size = self
+            .data_bytes
+            .saturating_add(DATA_SIZE_OFFSET as u32 + 4 - 8);
+        self.file.seek(SeekFrom::Start(RIFF_SIZE_OFFSET))?;
+        self.file.write_all(&riff_size.to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+        self.file.write_all(&self.data_bytes.to_le_bytes())?;
+        self.file.flush()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            error!("failed to finish WAV file: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sink_kinds() {
+        assert_eq!("device".parse(), Ok(SinkKind::Device));
+        assert_eq!("null".parse(), Ok(SinkKind::Null));
+        assert_eq!(
+            "wav:out.wav".parse(),
+            Ok(SinkKind::Wav(PathBuf::from("out.wav")))
+        );
+        // only the first colon separates the kind from the path
+        assert_eq!(
+            "wav:C:\\audio\\out.wav".parse(),
+            Ok(SinkKind::Wav(PathBuf::from("C:\\audio\\out.wav")))
+        );
+    }
+
+    #[test]
+    fn wav_needs_a_path() {
+        assert_eq!(
+            "wav:".parse::<SinkKind>(),
+            Err("expected device, null or wav:<path>, got 'wav:'".to_string())
+        );
+        assert_eq!(
+            "wav".parse::<SinkKind>(),
+            Err("expected device, null or wav:<path>, got 'wav'".to_string())
+        );
+    }
+
+    #[test]
+    fn kinds_are_case_sensitive() {
+        assert_eq!(
+            "Device".parse::<SinkKind>(),
+            Err("expected device, null or wav:<path>, got 'Device'".to_string())
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// how far ahead of the clock audio is queued for the device
+const NORMAL_LATENCY: f64 = 0.1; // seconds
+const LOW_LATENCY: f64 = 0.03; // seconds
+/// Upper bound for the adaptive target latency, in seconds.
+pub const MAX_LATENCY: f64 = 0.5;
+// growth per underrun, and decay per stable period back towards the base latency
+const GROWTH: f64 = 1.5;
+const DECAY: f64 = 0.9;
+const STABLE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Snapshot of audio buffering health.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AudioStats {
+    /// Times the device asked for samples and the buffer ran dry
+    pub underruns: u64,
+    /// Times the buffer was full and decoded samples had to be dropped
+    pub overruns: u64,
+    /// Decoded chunks discarded because the clock had already passed them
+    pub late_chunks: u64,
+    /// Current queueing target in seconds
+    pub target_latency: f64,
+    /// Audio queued for the device in seconds
+    pub buffered: f64,
+}
+
+impl fmt::Display for AudioStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "buffer {:.0}/{:.0} ms, {} underruns, {} overruns, {} late chunks",
+            self.buffered * 1000.0,
+            self.target_latency * 1000.0,
+            self.underruns,
+            self.overruns,
+            self.late_chunks
+        )
+    }
+}
+
+/// Counters shared by the playback thread, the sink callback and the player.
+pub struct AudioCounters {
+    underruns: AtomicU64,
+    overruns: AtomicU64,
+    late_chunks: AtomicU64,
+    // f64 bits, in seconds
+    target_latency: AtomicU64,
+    buffered: AtomicU64,
+}
+
+impl AudioCounters {
+    pub fn new() -> Self {
+        Self {
+            underruns: AtomicU64::new(0),
+            overruns: AtomicU64::new(0),
+            late_chunks: AtomicU64::new(0),
+            target_latency: AtomicU64::new(NORMAL_LATENCY.to_bits()),
+            buffered: AtomicU64::new(0),
+        }
+    }
+
+    pub fn underrun(&self) {
+        self.underruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn overrun(&self) {
+        self.overruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn late_chunk(&self) {
+        self.late_chunks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_buffered(&self, seconds: f64) {
+        self.buffered.store(seconds.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> AudioStats {
+        AudioStats {
+            underruns: self.underruns.load(Ordering::Relaxed),
+            overruns: self.overruns.load(Ordering::Relaxed),
+            late_chunks: self.late_chunks.load(Ordering::Relaxed),
+            target_latency: f64::from_bits(self.target_latency.load(Ordering::Relaxed)),
+            buffered: f64::from_bits(self.buffered.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Default for AudioCounters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Target latency that grows after underruns and shrinks back while playback is stable.
+pub struct AdaptiveLatency {
+    base: f64,
+    current: f64,
+    seen_underruns: u64,
+    last_change: Instant,
+}
+
+impl AdaptiveLatency {
+    pub fn new(low_latency: bool, counters: &AudioCounters) -> Self {
+        let base = if low_latency {
+            LOW_LATENCY
+        } else {
+            NORMAL_LATENCY
+        };
+        counters
+            .target_latency
+            .store(base.to_bits(), Ordering::Relaxed);
+        Self {
+            base,
+            current: base,
+            // counters outlive outputs, so only underruns from now on count
+            seen_underruns: counters.underruns.load(Ordering::Relaxed),
+            last_change: Instant::now(),
+        }
+    }
+
+    /// Adapts to the underruns counted so far and returns the target latency in seconds.
+    pub fn update(&mut self, counters: &AudioCounters) -> f64 {
+        let underruns = counters.underruns.load(Ordering::Relaxed);
+        let previous = self.current;
+        if underruns > self.seen_underruns {
+            self.seen_underruns = underruns;
+            self.current = (self.current * GROWTH).min(MAX_LATENCY);
+            self.last_change = Instant::now();
+        } else if self.current > self.base && self.last_change.elapsed() >= STABLE_PERIOD {
+            self.current = (self.current * DECAY).max(self.base);
+            self.last_change = Instant::now();
+        }
+        if self.current != previous {
+            counters
+                .target_latency
+                .store(self.current.to_bits(), Ordering::Relaxed);
+        }
+        self.current
+    }
+}
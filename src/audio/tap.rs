@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use parking_lot::Mutex;
+
+// history kept behind the newest sample; covers the queue ahead of the clock plus the longest
+// window a visualization looks back over
+const HISTORY: f64 = 3.0; // seconds
+
+/// Mono copy of the audio sent to the device, kept for visualizations.
+pub struct PcmTap {
+    enabled: AtomicBool,
+    inner: Mutex<TapBuffer>,
+}
+
+struct TapBuffer {
+    samples: VecDeque<f32>,
+    sample_rate: u32,
+    // media time just after the newest sample, on the same timeline as the clock
+    end_pts: f64,
+}
+
+impl PcmTap {
+    pub fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            inner: Mutex::new(TapBuffer {
+                samples: VecDeque::new(),
+                sample_rate: 48000,
+                end_pts: 0.0,
+            }),
+        }
+    }
+
+    /// Starts or stops copying audio; off by default so video playback pays nothing.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.inner.lock().samples.clear();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Appends an interleaved chunk starting at `pts`, mixed down to mono.
+    pub fn push(&self, samples: &[f32], channels: u16, sample_rate: u32, pts: f64) {
+        if !self.is_enabled() || channels == 0 {
+            return;
+        }
+        let channels = channels as usize;
+        let mut inner = self.inner.lock();
+        let frames = samples.len() / channels;
+        // a jump means a seek or device change, so the old history no longer lines up
+        let continuous = sample_rate == inner.sample_rate && (pts - inner.end_pts).abs() < 0.1;
+        if !continuous {
+            inner.samples.clear();
+            inner.sample_rate = sample_rate;
+        }
+        let scale = 1.0 / channels as f32;
+        inner.samples.extend(
+            samples
+                .chunks_exact(channels)
+                .map(|frame| frame.iter().sum::<f32>() * scale),
+        );
+        let capacity = (HISTORY * sample_rate as f64) as usize;
+        let excess = inner.samples.len().saturating_sub(capacity);
+        inner.samples.drain(..excess);
+        inner.end_pts = pts + frames as f64 / sample_rate as f64;
+    }
+
+    /// The `len` mono samples ending at media time `now`, zero-padded where nothing was
+    /// played, together with the sample rate.
+    pub fn window(&self, now: f64, len: usize) -> (Vec<f32>, u32) {
+        let inner = self.inner.lock();
+        let ahead = ((inner.end_pts - now) * inner.sample_rate as f64).round();
+        let end = inner.samples.len() as i64 - ahead.max(0.0) as i64;
+        let start = end - len as i64;
+        let window = (start..end)
+            .map(|index| {
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|index| inner.samples.get(index))
+                    .copied()
+                    .unwrap_or(0.0)
+            })
+            .collect();
+        (window, inner.sample_rate)
+    }
+}
+
+impl Default for PcmTap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
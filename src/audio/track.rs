@@ -0,0 +1,78 @@
+use std::fmt;
+
+use ffmpeg_next as ffmpeg;
+use tracing::warn;
+
+/// An audio stream that can be played, from the main file or an external one such as a dub.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioTrack {
+    pub path: String,
+    /// Stream index within `path`
+    pub stream_index: usize,
+    /// Delay of this track in seconds, added to the user audio delay
+    pub offset: f64,
+    /// Loaded from a separate file rather than embedded in the video
+    pub external: bool,
+    pub title: Option<String>,
+    pub language: Option<String>,
+    pub codec: String,
+    pub channels: u16,
+}
+
+impl fmt::Display for AudioTrack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.title.as_deref().unwrap_or(&self.codec))?;
+        if let Some(language) = &self.language {
+            write!(f, " [{}]", language)?;
+        }
+        write!(f, ", {} ch", self.channels)?;
+        if self.external {
+            write!(f, ", external")?;
+        }
+        Ok(())
+    }
+}
+
+/// Lists the audio streams of `path` in file order, each delayed by `offset` seconds.
+pub fn probe_tracks(path: &str, offset: f64, external: bool) -> Vec<AudioTrack> {
+    let ictx = match ffmpeg::format::input(path) {
+        Ok(ictx) => ictx,
+        Err(e) => {
+            warn!("failed to open audio file '{}': {:?}", path, e);
+            return Vec::new();
+        }
+    };
+    ictx.streams()
+        .filter(|stream| stream.parameters().medium() == ffmpeg::media::Type::Audio)
+        .filter_map(|stream| {
+            let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())
+                .ok()?
+                .decoder()
+                .audio()
+                .ok()?;
+            let metadata = stream.metadata();
+            Some(AudioTrack {
+                path: path.to_string(),
+                stream_index: stream.index(),
+                offset,
+                external,
+                title: metadata.get("title").map(str::to_string),
+                language: metadata.get("language").map(str::to_string),
+                codec: decoder.id().name().to_string(),
+                channels: decoder.channels(),
+            })
+        })
+        .collect()
+}
+
+/// Index in `tracks` of the stream FFmpeg considers the best audio of `path`.
+pub fn default_track(path: &str, tracks: &[AudioTrack]) -> Option<usize> {
+    let best = ffmpeg::format::input(path)
+        .ok()?
+        .streams()
+        .best(ffmpeg::media::Type::Audio)?
+        .index();
+    tracks
+        .iter()
+        .position(|track| track.path == path && track.stream_index == best)
+}
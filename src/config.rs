@@ -0,0 +1,110 @@
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use tracing::{error, warn};
+
+/// Directory holding persistent player settings, if one can be determined.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+    base.map(|dir| dir.join("prism-player"))
+}
+
+/// A flat `key = value` settings file; lines starting with `#` are comments.
+pub struct SettingsFile {
+    path: Option<PathBuf>,
+    values: BTreeMap<String, String>,
+}
+
+impl SettingsFile {
+    /// Loads `path`; a missing or unreadable file yields empty settings.
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let values = path
+            .as_deref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|content| parse(&content))
+            .unwrap_or_default();
+        Self { path, values }
+    }
+
+    /// Settings shared by every session, such as volume.
+    pub fn global() -> Self {
+        Self::open(config_dir().map(|dir| dir.join("settings.conf")))
+    }
+
+    /// Settings remembered for one media file, such as picture adjustments and delays.
+    pub fn for_media(media_path: &str) -> Self {
+        // key by canonical path so relative and absolute invocations share settings
+        let canonical = fs::canonicalize(media_path)
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| media_path.to_string());
+        let name = format!("{:016x}.conf", fnv1a(canonical.as_bytes()));
+        let mut settings = Self::open(config_dir().map(|dir| dir.join("files").join(name)));
+        settings.set("path", canonical);
+        settings
+    }
+
+    pub fn get<T: FromStr>(&self, key: &str) -> Option<T> {
+        let value = self.values.get(key)?;
+        match value.parse() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                warn!("ignoring invalid setting {} = {}", key, value);
+                None
+            }
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: impl fmt::Display) {
+        self.values.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.values.remove(key);
+    }
+
+    /// Writes the settings back to disk; failures are logged because they are never fatal.
+    pub fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let content: String = self
+            .values
+            .iter()
+            .map(|(key, value)| format!("{key} = {value}\n"))
+            .collect();
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, content));
+        if let Err(e) = result {
+            error!("failed to save settings to {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn parse(content: &str) -> BTreeMap<String, String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// Stable 64-bit FNV-1a hash, used to derive file names from media paths.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
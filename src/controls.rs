@@ -0,0 +1,352 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use parking_lot::RwLock;
+use tessera_ui::{Color, DimensionValue, Dp, tessera};
+use tessera_ui_basic_components::{
+    alignment::{CrossAxisAlignment, MainAxisAlignment},
+    column::{ColumnArgs, column},
+    fluid_glass::{FluidGlassArgs, fluid_glass},
+    row::{RowArgs, row},
+    shape_def::Shape,
+    slider::{SliderArgs, SliderState, slider},
+    surface::{SurfaceArgs, surface},
+    text::{TextArgs, text},
+};
+
+use crate::{
+    audio::gain::MAX_VOLUME,
+    media::{
+        SPEEDS, VideoPlayerState,
+        seek_bar::{SeekBarState, seek_bar},
+    },
+    window::WindowControl,
+};
+
+// the controls start fading once the mouse has been still this long
+const HIDE_AFTER: Duration = Duration::from_millis(2500);
+const FADE_DURATION: Duration = Duration::from_millis(300);
+
+/// Popup list opened from the control bar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Menu {
+    Speed,
+    AudioTrack,
+}
+
+/// Control bar state kept across frames.
+pub struct ControlsState {
+    seek_bar: Arc<SeekBarState>,
+    volume_slider: Arc<RwLock<SliderState>>,
+    menu: RwLock<Option<Menu>>,
+    last_activity: RwLock<Instant>,
+    last_cursor: RwLock<Option<(i32, i32)>>,
+}
+
+impl Default for ControlsState {
+    fn default() -> Self {
+        Self {
+            seek_bar: Default::default(),
+            volume_slider: Default::default(),
+            menu: RwLock::new(None),
+            last_activity: RwLock::new(Instant::now()),
+            last_cursor: RwLock::new(None),
+        }
+    }
+}
+
+impl ControlsState {
+    /// Notes where the cursor is; moving it brings the controls back.
+    pub fn track_cursor(&self, position: Option<(i32, i32)>) {
+        let mut last = self.last_cursor.write();
+        if position.is_some() && position != *last {
+            self.wake();
+        }
+        *last = position;
+    }
+
+    /// Shows the controls and restarts the hide timer.
+    pub fn wake(&self) {
+        *self.last_activity.write() = Instant::now();
+    }
+
+    /// 1.0 while the controls are in use, fading to 0.0 after the mouse goes still.
+    fn opacity(&self, pinned: bool) -> f32 {
+        if pinned {
+            self.wake();
+            return 1.0;
+        }
+        let idle = self
+            .last_activity
+            .read()
+            .elapsed()
+            .saturating_sub(HIDE_AFTER);
+        1.0 - (idle.as_secs_f32() / FADE_DURATION.as_secs_f32()).min(1.0)
+    }
+
+    /// Opens the menu, or closes it if it is already open.
+    fn toggle_menu(&self, menu: Menu) {
+        let mut open = self.menu.write();
+        *open = if *open == Some(menu) {
+            None
+        } else {
+            Some(menu)
+        };
+    }
+}
+
+/// Seek bar, transport, volume, speed and track menus, and fullscreen, along the bottom edge.
+#[tessera]
+pub fn control_bar(
+    state: Arc<ControlsState>,
+    player: Arc<RwLock<VideoPlayerState>>,
+    window: Arc<WindowControl>,
+) {
+    let menu = *state.menu.read();
+    // stay up while paused or in use, so the controls do not vanish under the cursor
+    let pinned = !player.read().is_playing() || menu.is_some() || state.seek_bar.is_active();
+    let opacity = state.opacity(pinned);
+    if opacity <= 0.0 {
+        return;
+    }
+
+    column(
+        ColumnArgs {
+            width: DimensionValue::FILLED,
+            cross_axis_alignment: CrossAxisAlignment::End,
+            ..Default::default()
+        },
+        move |scope| {
+            if let Some(menu) = menu {
+                let state = state.clone();
+                let player = player.clone();
+                scope.child(move || menu_panel(menu, state, player));
+            }
+            scope.child(move || {
+                fluid_glass(
+                    FluidGlassArgs {
+                        width: DimensionValue::FILLED,
+                        height: Dp(124.0).into(),
+                        refraction_height: 50.0,
+                        refraction_amount: 100.0 * opacity,
+                        blur_radius: 30.0 * opacity,
+                        shape: Shape::rounded_rectangle(Dp(25.0)),
+                        tint_color: Color::WHITE.with_alpha(0.1 * opacity),
+                        ..Default::default()
+                    },
+                    None,
+                    move || {
+                        column(
+                            ColumnArgs {
+                                width: DimensionValue::FILLED,
+                                ..Default::default()
+                            },
+                            move |scope| {
+                                let seek_bar_state = state.seek_bar.clone();
+                                let seek_bar_player = player.clone();
+                                scope.child(move || {
+                                    seek_bar(seek_bar_state, seek_bar_player, opacity)
+                                });
+                                scope.child(move || button_row(state, player, window, opacity));
+                            },
+                        );
+                    },
+                );
+            });
+        },
+    );
+}
+
+#[tessera]
+fn button_row(
+    state: Arc<ControlsState>,
+    player: Arc<RwLock<VideoPlayerState>>,
+    window: Arc<WindowControl>,
+    opacity: f32,
+) {
+    let (playing, muted, volume, speed) = {
+        let player = player.read();
+        (
+            player.is_playing(),
+            player.is_muted(),
+            player.volume(),
+            player.speed(),
+        )
+    };
+
+    row(
+        RowArgs {
+            width: DimensionValue::FILLED,
+            main_axis_alignment: MainAxisAlignment::SpaceBetween,
+            cross_axis_alignment: CrossAxisAlignment::Center,
+            ..Default::default()
+        },
+        move |scope| {
+            let left_state = state.clone();
+            let left_player = player.clone();
+            scope.child(move || {
+                let state = left_state;
+                let player = left_player;
+                row(
+                    RowArgs {
+                        cross_axis_alignment: CrossAxisAlignment::Center,
+                        ..Default::default()
+                    },
+                    move |scope| {
+                        let target = player.clone();
+                        scope.child(move || {
+                            control_button("Prev".into(), opacity, move || {
+                                target.write().previous_chapter()
+                            })
+                        });
+                        let target = player.clone();
+                        let label = if playing { "Pause" } else { "Play" };
+                        scope.child(move || {
+                            control_button(label.into(), opacity, move || target.write().toggle())
+                        });
+                        let target = player.clone();
+                        scope.child(move || {
+                            control_button("Next".into(), opacity, move || {
+                                target.write().next_chapter()
+                            })
+                        });
+                        let target = player.clone();
+                        let label = if muted { "Unmute" } else { "Mute" };
+                        scope.child(move || {
+                            control_button(label.into(), opacity, move || {
+                                target.write().toggle_mute()
+                            })
+                        });
+                        scope.child(move || {
+                            slider(
+                                SliderArgs {
+                                    value: volume / MAX_VOLUME,
+                                    on_change: Arc::new(move |value| {
+                                        player.write().set_volume(value * MAX_VOLUME)
+                                    }),
+                                    width: Dp(120.0),
+                                    ..Default::default()
+                                },
+                                state.volume_slider.clone(),
+                            )
+                        });
+                    },
+                );
+            });
+
+            scope.child(move || {
+                row(
+                    RowArgs {
+                        cross_axis_alignment: CrossAxisAlignment::Center,
+                        ..Default::default()
+                    },
+                    move |scope| {
+                        let menu_state = state.clone();
+                        scope.child(move || {
+                            control_button(format!("{}x", speed), opacity, move || {
+                                menu_state.toggle_menu(Menu::Speed)
+                            })
+                        });
+                        if player.read().audio_tracks().len() > 1 {
+                            let menu_state = state.clone();
+                            scope.child(move || {
+                                control_button("Audio".into(), opacity, move || {
+                                    menu_state.toggle_menu(Menu::AudioTrack)
+                                })
+                            });
+                        }
+                        let label = if window.is_fullscreen() {
+                            "Exit fullscreen"
+                        } else {
+                            "Fullscreen"
+                        };
+                        scope.child(move || {
+                            control_button(label.into(), opacity, move || {
+                                window.toggle_fullscreen()
+                            })
+                        });
+                    },
+                );
+            });
+        },
+    );
+}
+
+/// Options of the open menu; picking one applies it and closes the menu.
+#[tessera]
+fn menu_panel(menu: Menu, state: Arc<ControlsState>, player: Arc<RwLock<VideoPlayerState>>) {
+    let options: Vec<(String, bool)> = {
+        let player = player.read();
+        match menu {
+            Menu::Speed => SPEEDS
+                .iter()
+                .map(|&speed| (format!("{}x", speed), speed == player.speed()))
+                .collect(),
+            Menu::AudioTrack => player
+                .audio_tracks()
+                .iter()
+                .enumerate()
+                .map(|(index, track)| (track.to_string(), Some(index) == player.audio_track()))
+                .collect(),
+        }
+    };
+
+    fluid_glass(
+        FluidGlassArgs {
+            refraction_height: 50.0,
+            refraction_amount: 100.0,
+            blur_radius: 30.0,
+            shape: Shape::rounded_rectangle(Dp(20.0)),
+            tint_color: Color::WHITE.with_alpha(0.1),
+            ..Default::default()
+        },
+        None,
+        move || {
+            column(ColumnArgs::default(), move |scope| {
+                for (index, (label, selected)) in options.into_iter().enumerate() {
+                    let state = state.clone();
+                    let player = player.clone();
+                    let label = if selected {
+                        format!("> {}", label)
+                    } else {
+                        label
+                    };
+                    scope.child(move || {
+                        control_button(label, 1.0, move || {
+                            let mut player = player.write();
+                            match menu {
+                                Menu::Speed => player.set_speed(SPEEDS[index]),
+                                Menu::AudioTrack => player.set_audio_track(index),
+                            }
+                            *state.menu.write() = None;
+                        })
+                    });
+                }
+            });
+        },
+    );
+}
+
+#[tessera]
+fn control_button(label: String, opacity: f32, on_click: impl Fn() + Send + Sync + 'static) {
+    surface(
+        SurfaceArgs {
+            style: Color::TRANSPARENT.into(),
+            shape: Shape::rounded_rectangle(Dp(12.0)),
+            padding: Dp(10.0),
+            on_click: Some(Arc::new(on_click)),
+            ..Default::default()
+        },
+        None,
+        move || {
+            text(TextArgs {
+                text: label,
+                size: Dp(16.0),
+                color: Color::WHITE.with_alpha(opacity),
+                ..Default::default()
+            });
+        },
+    );
+}
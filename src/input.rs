@@ -0,0 +1,386 @@
+use std::{collections::HashMap, fs, io, path::Path, str::FromStr};
+
+use tessera_ui::winit::keyboard::{Key, NamedKey};
+use tracing::{info, warn};
+
+use crate::{config::config_dir, media::picture::PictureProperty};
+
+/// Something the player can be told to do, from a key binding or elsewhere.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlayerCommand {
+    TogglePause,
+    /// Relative seek in seconds
+    Seek(f64),
+    /// Relative volume change in percent
+    Volume(f32),
+    ToggleMute,
+    ToggleFullscreen,
+    /// Window size in percent of the video size
+    WindowScale(f64),
+    ToggleAlwaysOnTop,
+    FrameStep,
+    FrameBackStep,
+    SpeedUp,
+    SpeedDown,
+    NextChapter,
+    PreviousChapter,
+    /// Relative audio delay change in seconds
+    AudioDelay(f64),
+    CycleAudioDevice,
+    ToggleNightMode,
+    CycleEqualizer,
+    AudioStats,
+    CycleAudioTrack,
+    CycleSecondaryTrack,
+    /// Relative main track volume change in percent
+    MainVolume(f32),
+    /// Relative secondary track volume change in percent
+    SecondaryVolume(f32),
+    ToggleDucking,
+    CycleVisualization,
+    Picture(PictureProperty, i32),
+    ResetPicture,
+    TogglePicturePanel,
+    CycleDeinterlace,
+    CycleScaling,
+    ToggleShaders,
+    /// Does nothing; binding a key to it removes a default binding
+    Ignore,
+}
+
+impl FromStr for PlayerCommand {
+    type Err = String;
+
+    /// Parses a command as written in `input.conf`, e.g. `seek -5` or `picture gamma 1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        let number = |index: usize| -> Result<f64, String> {
+            args.get(index)
+                .and_then(|arg| arg.parse().ok())
+                .ok_or_else(|| format!("'{}' needs a number", name))
+        };
+        let command = match name {
+            "pause" => PlayerCommand::TogglePause,
+            "seek" => PlayerCommand::Seek(number(0)?),
+            "volume" => PlayerCommand::Volume(number(0)? as f32),
+            "mute" => PlayerCommand::ToggleMute,
+            "fullscreen" => PlayerCommand::ToggleFullscreen,
+            "window-scale" => PlayerCommand::WindowScale(number(0)?),
+            "ontop" => PlayerCommand::ToggleAlwaysOnTop,
+            "frame-step" => PlayerCommand::FrameStep,
+            "frame-back-step" => PlayerCommand::FrameBackStep,
+            "speed-up" => PlayerCommand::SpeedUp,
+            "speed-down" => PlayerCommand::SpeedDown,
+            "next-chapter" => PlayerCommand::NextChapter,
+            "previous-chapter" => PlayerCommand::PreviousChapter,
+            "audio-delay" => PlayerCommand::AudioDelay(number(0)?),
+            "audio-device" => PlayerCommand::CycleAudioDevice,
+            "night-mode" => PlayerCommand::ToggleNightMode,
+            "equalizer" => PlayerCommand::CycleEqualizer,
+            "audio-stats" => PlayerCommand::AudioStats,
+            "audio-track" => PlayerCommand::CycleAudioTrack,
+            "secondary-track" => PlayerCommand::CycleSecondaryTrack,
+            "main-volume" => PlayerCommand::MainVolume(number(0)? as f32),
+            "secondary-volume" => PlayerCommand::SecondaryVolume(number(0)? as f32),
+            "ducking" => PlayerCommand::ToggleDucking,
+            "visualization" => PlayerCommand::CycleVisualization,
+            "picture" => {
+                let property = args
+                    .first()
+                    .and_then(|arg| PictureProperty::ALL.into_iter().find(|p| p.key() == *arg))
+                    .ok_or_else(|| {
+                        "'picture' needs brightness, contrast, saturation, gamma or hue".to_string()
+                    })?;
+                PlayerCommand::Picture(property, number(1)? as i32)
+            }
+            "picture-reset" => PlayerCommand::ResetPicture,
+            "picture-panel" => PlayerCommand::TogglePicturePanel,
+            "deinterlace" => PlayerCommand::CycleDeinterlace,
+            "scaling" => PlayerCommand::CycleScaling,
+            "shaders" => PlayerCommand::ToggleShaders,
+            "ignore" => PlayerCommand::Ignore,
+            _ => return Err(format!("unknown command '{}'", s)),
+        };
+        Ok(command)
+    }
+}
+
+// `#` starts a comment in input.conf, so it is bound by name
+const SHARP: &str = "sharp";
+
+// key names of the keys that do not type a character
+const NAMED_KEYS: [(&str, NamedKey); 13] = [
+    ("space", NamedKey::Space),
+    ("left", NamedKey::ArrowLeft),
+    ("right", NamedKey::ArrowRight),
+    ("up", NamedKey::ArrowUp),
+    ("down", NamedKey::ArrowDown),
+    ("backspace", NamedKey::Backspace),
+    ("enter", NamedKey::Enter),
+    ("escape", NamedKey::Escape),
+    ("tab", NamedKey::Tab),
+    ("home", NamedKey::Home),
+    ("end", NamedKey::End),
+    ("pageup", NamedKey::PageUp),
+    ("pagedown", NamedKey::PageDown),
+];
+
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("space", "pause"),
+    ("left", "seek -5"),
+    ("right", "seek 5"),
+    ("up", "volume 5"),
+    ("down", "volume -5"),
+    ("f", "fullscreen"),
+    // shifted 1, 2 and 3 on US layouts
+    ("!", "window-scale 50"),
+    ("@", "window-scale 100"),
+    (SHARP, "window-scale 200"),
+    ("T", "ontop"),
+    ("m", "mute"),
+    (".", "frame-step"),
+    (",", "frame-back-step"),
+    ("]", "speed-up"),
+    ("[", "speed-down"),
+    ("-", "audio-delay -0.1"),
+    ("=", "audio-delay 0.1"),
+    ("+", "audio-delay 0.1"),
+    ("o", "audio-device"),
+    ("n", "night-mode"),
+    ("q", "equalizer"),
+    ("i", "audio-stats"),
+    ("a", "audio-track"),
+    ("c", "secondary-track"),
+    ("j", "secondary-volume -10"),
+    ("k", "secondary-volume 10"),
+    ("J", "main-volume -10"),
+    ("K", "main-volume 10"),
+    ("d", "ducking"),
+    ("v", "visualization"),
+    // mpv layout
+    ("1", "picture contrast -1"),
+    ("2", "picture contrast 1"),
+    ("3", "picture brightness -1"),
+    ("4", "picture brightness 1"),
+    ("5", "picture gamma -1"),
+    ("6", "picture gamma 1"),
+    ("7", "picture saturation -1"),
+    ("8", "picture saturation 1"),
+    ("9", "picture hue -1"),
+    ("0", "picture hue 1"),
+    ("backspace", "picture-reset"),
+    ("e", "picture-panel"),
+    ("D", "deinterlace"),
+    ("s", "scaling"),
+    ("x", "shaders"),
+];
+
+/// Maps keys to player commands: the defaults, overridden by `input.conf`.
+pub struct KeyBindings {
+    bindings: HashMap<String, PlayerCommand>,
+}
+
+impl KeyBindings {
+    /// The default bindings with those of `path` on top, or of `input.conf` in the config
+    /// directory if no path is given.
+    pub fn load(path: Option<&Path>) -> Self {
+        let explicit = path.is_some();
+        let default_path = config_dir().map(|dir| dir.join("input.conf"));
+        let Some(path) = path.or(default_path.as_deref()) else {
+            return Self::parse("", Path::new(""));
+        };
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                // the default file is optional, an explicit one is not
+                if explicit || e.kind() != io::ErrorKind::NotFound {
+                    warn!("failed to read key bindings from {}: {}", path.display(), e);
+                }
+                return Self::parse("", path);
+            }
+        };
+        let bindings = Self::parse(&content, path);
+        info!("loaded key bindings from {}", path.display());
+        bindings
+    }
+
+    /// The default bindings with the lines of `content`, read from `source`, on top.
+    fn parse(content: &str, source: &Path) -> Self {
+        let mut bindings: HashMap<String, PlayerCommand> = DEFAULT_BINDINGS
+            .iter()
+            .map(|&(key, command)| {
+                let command = command.parse().expect("default bindings are valid");
+                (key.to_string(), command)
+            })
+            .collect();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, command) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            if !is_key_name(key) {
+                warn!("{}:{}: unknown key '{}'", source.display(), number + 1, key);
+                continue;
+            }
+            match command.trim().parse() {
+                Ok(command) => {
+                    bindings.insert(key.to_string(), command);
+                }
+                Err(e) => warn!("{}:{}: {}", source.display(), number + 1, e),
+            }
+        }
+        Self { bindings }
+    }
+
+    /// The command bound to `key`, if any.
+    pub fn command(&self, key: &Key) -> Option<PlayerCommand> {
+        let name = match key {
+            Key::Character(c) if c.as_str() == "#" => SHARP,
+            Key::Character(c) => c.as_str(),
+            Key::Named(named) => NAMED_KEYS.iter().find(|(_, key)| key == named)?.0,
+            _ => return None,
+        };
+        self.bindings
+            .get(name)
+            .copied()
+            .filter(|&command| command != PlayerCommand::Ignore)
+    }
+}
+
+/// Whether `name` is a single character, `sharp` or one of [`NAMED_KEYS`].
+fn is_key_name(name: &str) -> bool {
+    name.chars().count() == 1 || name == SHARP || NAMED_KEYS.iter().any(|(named, _)| *named == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> KeyBindings {
+        KeyBindings::parse(content, Path::new("input.conf"))
+    }
+
+    fn character(c: &str) -> Key {
+        Key::Character(c.into())
+    }
+
+    #[test]
+    fn keeps_unlisted_defaults() {
+        let bindings = parse("l seek 10\n");
+        assert_eq!(
+            bindings.command(&Key::Named(NamedKey::Space)),
+            Some(PlayerCommand::TogglePause)
+        );
+        assert_eq!(
+            bindings.command(&character("1")),
+            Some(PlayerCommand::Picture(PictureProperty::Contrast, -1))
+        );
+        assert_eq!(
+            bindings.command(&character("l")),
+            Some(PlayerCommand::Seek(10.0))
+        );
+    }
+
+    #[test]
+    fn input_conf_overrides_default_bindings() {
+        let bindings = parse("# key    command\n\nleft     seek -10\nf picture gamma 2\n");
+        assert_eq!(
+            bindings.command(&Key::Named(NamedKey::ArrowLeft)),
+            Some(PlayerCommand::Seek(-10.0))
+        );
+        assert_eq!(
+            bindings.command(&character("f")),
+            Some(PlayerCommand::Picture(PictureProperty::Gamma, 2))
+        );
+        // the later of two lines for one key wins
+        let bindings = parse("m pause\nm ontop\n");
+        assert_eq!(
+            bindings.command(&character("m")),
+            Some(PlayerCommand::ToggleAlwaysOnTop)
+        );
+    }
+
+    #[test]
+    fn ignore_removes_a_default_binding() {
+        let bindings = parse("d ignore");
+        assert_eq!(bindings.command(&character("d")), None);
+        assert_eq!(
+            bindings.command(&character("D")),
+            Some(PlayerCommand::CycleDeinterlace)
+        );
+    }
+
+    #[test]
+    fn named_keys_map_to_winit_keys() {
+        let bindings = parse("pageup next-chapter\npagedown previous-chapter\nescape fullscreen\n");
+        assert_eq!(
+            bindings.command(&Key::Named(NamedKey::PageUp)),
+            Some(PlayerCommand::NextChapter)
+        );
+        assert_eq!(
+            bindings.command(&Key::Named(NamedKey::PageDown)),
+            Some(PlayerCommand::PreviousChapter)
+        );
+        assert_eq!(
+            bindings.command(&Key::Named(NamedKey::Escape)),
+            Some(PlayerCommand::ToggleFullscreen)
+        );
+        // unbound named keys do nothing
+        assert_eq!(bindings.command(&Key::Named(NamedKey::Home)), None);
+    }
+
+    #[test]
+    fn sharp_stands_for_the_comment_character() {
+        assert_eq!(
+            parse("").command(&character("#")),
+            Some(PlayerCommand::WindowScale(200.0))
+        );
+        // a line starting with # is a comment, so it cannot rebind the key
+        let bindings = parse("# mute\nsharp mute\n");
+        assert_eq!(
+            bindings.command(&character("#")),
+            Some(PlayerCommand::ToggleMute)
+        );
+        assert_eq!(
+            bindings.command(&character("sharp")),
+            Some(PlayerCommand::ToggleMute)
+        );
+    }
+
+    #[test]
+    fn invalid_lines_keep_the_previous_binding() {
+        // an unknown key name, an unknown command and a missing argument
+        let bindings = parse("ctrl+m mute\nm jump\nspace seek\n");
+        assert_eq!(
+            bindings.command(&character("m")),
+            Some(PlayerCommand::ToggleMute)
+        );
+        assert_eq!(
+            bindings.command(&Key::Named(NamedKey::Space)),
+            Some(PlayerCommand::TogglePause)
+        );
+        assert!(!bindings.bindings.contains_key("ctrl+m"));
+    }
+
+    #[test]
+    fn command_errors_explain_the_problem() {
+        assert_eq!(
+            "jump 5".parse::<PlayerCommand>(),
+            Err("unknown command 'jump 5'".to_string())
+        );
+        assert_eq!(
+            "seek forward".parse::<PlayerCommand>(),
+            Err("'seek' needs a number".to_string())
+        );
+        let picture = "'picture' needs brightness, contrast, saturation, gamma or hue".to_string();
+        assert_eq!("picture".parse::<PlayerCommand>(), Err(picture.clone()));
+        assert_eq!("picture sharpness 1".parse::<PlayerCommand>(), Err(picture));
+        assert_eq!(
+            "picture gamma up".parse::<PlayerCommand>(),
+            Err("'picture' needs a number".to_string())
+        );
+    }
+}
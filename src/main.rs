@@ -1,8 +1,13 @@
 mod audio;
 mod color;
+mod config;
+mod controls;
+mod input;
 mod media;
+mod osd;
+mod window;
 
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use mimalloc::MiMalloc;
 
@@ -11,7 +16,7 @@ static GLOBAL: MiMalloc = MiMalloc;
 
 use clap::Parser;
 use parking_lot::RwLock;
-use tessera_ui::{Color, DimensionValue, Dp, Renderer, shard, tessera};
+use tessera_ui::{Color, DimensionValue, Dp, Renderer, shard, tessera, winit::event::ElementState};
 use tessera_ui_basic_components::{
     RippleState,
     alignment::Alignment,
@@ -21,11 +26,33 @@ use tessera_ui_basic_components::{
     surface::{SurfaceArgs, surface},
     text::{TextArgs, text},
 };
-use tracing::error;
+use tracing::{error, info};
 
 use crate::{
+    audio::{
+        channels::{ChannelCount, ChannelMap, DownmixMode},
+        dsp::{DspSettings, Equalizer},
+        loudness::{LoudnessOptions, ReplayGainMode},
+        player::AudioOptions,
+        resample::{DitherMode, ResampleQuality, ResamplerSettings},
+        sink::{SinkKind, output_devices},
+    },
     color::BACKGROUND_COLOR,
-    media::{VideoPlayerArgs, VideoPlayerState, pipeline::VideoPipeline, video_player},
+    controls::{ControlsState, control_bar},
+    input::{KeyBindings, PlayerCommand},
+    media::{
+        VideoPlayerArgs, VideoPlayerOptions, VideoPlayerState,
+        clock::ClockMode,
+        filter::DeinterlaceMode,
+        picture::{PictureAdjustments, PictureProperty},
+        pipeline::VideoPipeline,
+        scaling::ScalingMode,
+        seek_bar::SeekBarPipeline,
+        video_player,
+        visualizer::{Visualization, VisualizerPipeline},
+    },
+    osd::{Osd, OsdOptions, OsdPosition, osd_layer},
+    window::{WindowControl, WindowOptions},
 };
 
 /// Simple video player application
@@ -33,8 +60,132 @@ use crate::{
 #[command(version, about, long_about = None)]
 struct Args {
     /// Path to the video file to play
-    #[arg(short, long)]
-    video_path: String,
+    #[arg(short, long, required_unless_present = "list_audio_devices")]
+    video_path: Option<String>,
+    /// Component that drives A/V synchronization
+    #[arg(long, value_enum, default_value_t = ClockMode::Audio)]
+    clock: ClockMode,
+    /// Deinterlacing mode for interlaced sources
+    #[arg(long, value_enum, default_value_t = DeinterlaceMode::Auto)]
+    deinterlace: DeinterlaceMode,
+    /// FFmpeg filtergraph applied to decoded video, e.g. "hqdn3d,eq=contrast=1.1"
+    #[arg(long)]
+    vf: Option<String>,
+    /// Separate audio file to play instead of the embedded audio, e.g. a dub
+    #[arg(long, value_name = "PATH")]
+    audio_file: Option<PathBuf>,
+    /// Delay of --audio-file in seconds; positive plays it later
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 0.0,
+        allow_hyphen_values = true
+    )]
+    audio_file_delay: f64,
+    /// Track number, as listed with the A key, to mix over the main track, e.g. a commentary
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    secondary_track: Option<u32>,
+    /// Volume of the main track in percent before mixing
+    #[arg(long, value_name = "PERCENT", default_value_t = 100.0)]
+    main_volume: f32,
+    /// Volume of the secondary track in percent before mixing
+    #[arg(long, value_name = "PERCENT", default_value_t = 100.0)]
+    secondary_volume: f32,
+    /// Keep the main track at full volume while the secondary one is active
+    #[arg(long)]
+    no_ducking: bool,
+    /// FFmpeg filtergraph applied to decoded audio, e.g. "loudnorm"
+    #[arg(long)]
+    af: Option<String>,
+    /// Audio destination: device, null (discard at real-time speed) or wav:<path> (record)
+    #[arg(long, default_value = "device")]
+    audio_output: SinkKind,
+    /// Audio output device name; see --list-audio-devices
+    #[arg(long)]
+    audio_device: Option<String>,
+    /// Audio output sample rate in Hz, instead of the device default
+    #[arg(long, value_name = "HZ")]
+    audio_rate: Option<u32>,
+    /// Audio device buffer size in frames; smaller lowers latency but risks dropouts
+    #[arg(long, value_name = "FRAMES")]
+    audio_buffer: Option<u32>,
+    /// Queue less audio ahead of the device; lower latency, more risk of dropouts
+    #[arg(long)]
+    low_latency: bool,
+    /// Output channels: auto, mono, stereo, 5.1, 7.1 or a count; auto keeps surround sources
+    #[arg(long, default_value = "auto")]
+    audio_channels: ChannelCount,
+    /// Downmix matrix used when the source has more channels than the output
+    #[arg(long, value_enum, default_value_t = DownmixMode::Standard)]
+    downmix: DownmixMode,
+    /// Channel rearrangement: normal, swap, mono, or output-to-source indices like 0,1,4,5,2,3
+    #[arg(long, default_value = "normal")]
+    channel_map: ChannelMap,
+    /// Sample rate conversion quality; high uses the SoX resampler when FFmpeg has it
+    #[arg(long, value_enum, default_value_t = ResampleQuality::Default)]
+    resample_quality: ResampleQuality,
+    /// Length of the resampling filter in taps, overriding the quality preset; swresample
+    /// accepts up to 1024
+    #[arg(long, value_name = "TAPS", value_parser = clap::value_parser!(u32).range(1..=1024))]
+    resample_filter_length: Option<u32>,
+    /// Dither added when the output device takes 16-bit samples
+    #[arg(long, value_enum, default_value_t = DitherMode::Triangular)]
+    dither: DitherMode,
+    /// Apply the ReplayGain track or album gain from the file tags
+    #[arg(long, value_enum, default_value_t = ReplayGainMode::Off)]
+    replaygain: ReplayGainMode,
+    /// Normalize loudness in real time (EBU R128) when no ReplayGain tag applies
+    #[arg(long)]
+    normalize: bool,
+    /// Target loudness for ReplayGain and normalization, in LUFS
+    #[arg(long, default_value_t = -18.0, allow_hyphen_values = true)]
+    loudness_target: f32,
+    /// Compress loud passages so dialog and effects sit closer together
+    #[arg(long)]
+    night_mode: bool,
+    /// Equalizer: a preset (flat, bass, treble, vocal, loudness), ten band gains in dB, or
+    /// parametric bands like "100:+3:0.7,high:10000:-2"
+    #[arg(long)]
+    eq: Option<Equalizer>,
+    /// Print the available audio output devices and exit
+    #[arg(long)]
+    list_audio_devices: bool,
+    /// WGSL post-processing shader pass; repeat to chain several in order
+    #[arg(long = "shader", value_name = "PATH")]
+    shaders: Vec<PathBuf>,
+    /// Scaling kernel used to fit the video to the window
+    #[arg(long, value_enum, default_value_t = ScalingMode::Bilinear)]
+    scale: ScalingMode,
+    /// Sharpening strength for the CAS scaler, from 0.0 to 1.0
+    #[arg(long, default_value_t = 0.5)]
+    sharpness: f32,
+    /// What to show for audio-only files; cover art falls back to the spectrum if there is none
+    #[arg(long, value_enum, default_value_t = Visualization::Cover)]
+    visualization: Visualization,
+    /// Start in fullscreen
+    #[arg(long)]
+    fullscreen: bool,
+    /// Initial window size in percent of the video size, shrunk to fit the screen
+    #[arg(long, value_name = "PERCENT", default_value_t = 100.0)]
+    window_scale: f64,
+    /// Keep the default window size instead of fitting the window to the video
+    #[arg(long)]
+    no_window_fit: bool,
+    /// Keep the window above other windows
+    #[arg(long)]
+    ontop: bool,
+    /// Key bindings file, instead of input.conf in the config directory
+    #[arg(long, value_name = "PATH")]
+    input_conf: Option<PathBuf>,
+    /// How long on-screen messages stay visible, in seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 1.5)]
+    osd_duration: f64,
+    /// Where on-screen messages appear
+    #[arg(long, value_enum, default_value_t = OsdPosition::TopLeft)]
+    osd_position: OsdPosition,
+    /// Text size of on-screen messages in dp
+    #[arg(long, value_name = "DP", default_value_t = 20.0)]
+    osd_font_size: f64,
 }
 
 fn main() {
@@ -50,14 +201,87 @@ fn main() {
         .with_env_filter(filter)
         .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
         .init();
-    let video_player_state = VideoPlayerState::new(&args.video_path);
+    if args.list_audio_devices {
+        for name in output_devices() {
+            println!("{name}");
+        }
+        return;
+    }
+    let video_path = args.video_path.expect("clap requires a video path");
+    let options = VideoPlayerOptions {
+        clock_mode: args.clock,
+        deinterlace: args.deinterlace,
+        video_filter: args.vf,
+        shaders: args.shaders,
+        scaling: args.scale,
+        sharpness: args.sharpness,
+        visualization: args.visualization,
+        audio_file: args.audio_file,
+        audio_file_delay: args.audio_file_delay,
+        secondary_track: args.secondary_track.map(|n| n as usize - 1),
+        audio: AudioOptions {
+            filter: args.af,
+            sink: args.audio_output,
+            device: args.audio_device,
+            sample_rate: args.audio_rate,
+            buffer_size: args.audio_buffer,
+            low_latency: args.low_latency,
+            channels: args.audio_channels,
+            downmix: args.downmix,
+            channel_map: args.channel_map,
+            resampler: ResamplerSettings {
+                quality: args.resample_quality,
+                filter_length: args.resample_filter_length,
+            },
+            dither: args.dither,
+            loudness: LoudnessOptions {
+                replay_gain: args.replaygain,
+                normalize: args.normalize,
+                target: args.loudness_target,
+            },
+            dsp: DspSettings {
+                night_mode: args.night_mode,
+                equalizer: args.eq.unwrap_or_default(),
+            },
+            main_volume: args.main_volume,
+            secondary_volume: args.secondary_volume,
+            ducking: !args.no_ducking,
+            ..Default::default()
+        },
+    };
+    let video_player_state = VideoPlayerState::new(&video_path, options);
     let video_player_state = Arc::new(RwLock::new(video_player_state));
+    let window_options = WindowOptions {
+        scale: (!args.no_window_fit).then_some(args.window_scale / 100.0),
+        fullscreen: args.fullscreen,
+        always_on_top: args.ontop,
+    };
+    let picture_size = video_player_state.read().picture_size();
+    let window = Arc::new(WindowControl::new(window_options, picture_size));
+    let bindings = Arc::new(KeyBindings::load(args.input_conf.as_deref()));
+    let osd = Arc::new(Osd::new(OsdOptions {
+        duration: Duration::from_secs_f64(args.osd_duration.max(0.0)),
+        position: args.osd_position,
+        font_size: args.osd_font_size,
+    }));
     Renderer::run(
-        || app(video_player_state.clone()),
+        || {
+            app(
+                video_player_state.clone(),
+                window.clone(),
+                bindings.clone(),
+                osd.clone(),
+            )
+        },
         |app| {
+            window.attach(app.window.clone());
             tessera_ui_basic_components::pipelines::register_pipelines(app);
             let video_pipeline = VideoPipeline::new(app.sample_count);
             app.drawer.pipeline_registry.register(video_pipeline);
+            let visualizer_pipeline = VisualizerPipeline::new(app.sample_count);
+            app.drawer.pipeline_registry.register(visualizer_pipeline);
+            let seek_bar_pipeline = SeekBarPipeline::new(app.sample_count);
+            app.drawer.pipeline_registry.register(seek_bar_pipeline);
         },
     )
     .unwrap_or_else(|e| error!("App failed to run: {e}"));
@@ -65,20 +289,286 @@ fn main() {
 
 struct AppState {
     scrim_ripple_state: Arc<RippleState>,
+    controls_state: Arc<ControlsState>,
+    show_picture_panel: RwLock<bool>,
 }
 
 impl Default for AppState {
     fn default() -> Self {
         let scrim_ripple_state = Default::default();
-        Self { scrim_ripple_state }
+        Self {
+            scrim_ripple_state,
+            controls_state: Default::default(),
+            show_picture_panel: RwLock::new(false),
+        }
+    }
+}
+
+/// Carries out `command` and reports what changed on the OSD and in the log.
+fn run_command(
+    state: &AppState,
+    video_player_state: &RwLock<VideoPlayerState>,
+    window: &WindowControl,
+    osd: &Osd,
+    command: PlayerCommand,
+) {
+    let mut player = video_player_state.write();
+    let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+    let message = match command {
+        PlayerCommand::TogglePause => {
+            player.toggle();
+            Some(
+                if player.is_playing() {
+                    "Playing"
+                } else {
+                    "Paused"
+                }
+                .to_string(),
+            )
+        }
+        PlayerCommand::Seek(seconds) => {
+            let position = player.position();
+            player.seek(position + seconds);
+            osd.show_progress();
+            None
+        }
+        PlayerCommand::Volume(step) => {
+            player.set_volume(player.volume() + step);
+            Some(format!("Volume {:.0}%", player.volume()))
+        }
+        PlayerCommand::ToggleMute => {
+            player.toggle_mute();
+            Some(
+                if player.is_muted() {
+                    "Muted"
+                } else {
+                    "Unmuted"
+                }
+                .to_string(),
+            )
+        }
+        PlayerCommand::ToggleFullscreen => {
+            window.toggle_fullscreen();
+            None
+        }
+        PlayerCommand::WindowScale(percent) => {
+            window.set_scale(percent / 100.0);
+            Some(format!("Window size {}%", percent))
+        }
+        PlayerCommand::ToggleAlwaysOnTop => {
+            let enabled = !window.is_always_on_top();
+            window.set_always_on_top(enabled);
+            Some(format!("Always on top {}", on_off(enabled)))
+        }
+        PlayerCommand::FrameStep | PlayerCommand::FrameBackStep => {
+            player.step_frame(command == PlayerCommand::FrameStep);
+            osd.show_progress();
+            None
+        }
+        PlayerCommand::SpeedUp => Some(format!("Speed {}x", player.step_speed(true))),
+        PlayerCommand::SpeedDown => Some(format!("Speed {}x", player.step_speed(false))),
+        PlayerCommand::NextChapter | PlayerCommand::PreviousChapter => {
+            if command == PlayerCommand::NextChapter {
+                player.next_chapter();
+            } else {
+                player.previous_chapter();
+            }
+            osd.show_progress();
+            let position = player.position();
+            player
+                .chapter_at(position)
+                .and_then(|chapter| chapter.title.clone())
+                .map(|title| format!("Chapter: {}", title))
+        }
+        PlayerCommand::AudioDelay(step) => {
+            player.set_audio_delay(player.audio_delay() + step);
+            Some(format!(
+                "Audio delay {:+.0} ms",
+                player.audio_delay() * 1000.0
+            ))
+        }
+        PlayerCommand::CycleAudioDevice => Some(match player.cycle_audio_device() {
+            Some(name) => format!("Audio device: {}", name),
+            None => "Audio device: system default".to_string(),
+        }),
+        PlayerCommand::ToggleNightMode => {
+            let enabled = !player.night_mode();
+            player.set_night_mode(enabled);
+            Some(format!("Night mode {}", on_off(enabled)))
+        }
+        PlayerCommand::CycleEqualizer => Some(format!("Equalizer: {}", player.cycle_eq_preset())),
+        PlayerCommand::AudioStats => Some(format!("Audio: {}", player.audio_stats())),
+        PlayerCommand::CycleAudioTrack => Some(match player.cycle_audio_track() {
+            Some(index) => format!(
+                "Audio {}/{}: {}",
+                index + 1,
+                player.audio_tracks().len(),
+                player.audio_tracks()[index]
+            ),
+            None => "No audio tracks".to_string(),
+        }),
+        PlayerCommand::CycleSecondaryTrack => Some(match player.cycle_secondary_track() {
+            Some(index) => format!("Secondary audio: {}", player.audio_tracks()[index]),
+            None => "Secondary audio off".to_string(),
+        }),
+        PlayerCommand::MainVolume(step) => {
+            player.set_main_volume(player.main_volume() + step);
+            Some(format!("Main volume {:.0}%", player.main_volume()))
+        }
+        PlayerCommand::SecondaryVolume(step) => {
+            player.set_secondary_volume(player.secondary_volume() + step);
+            Some(format!(
+                "Secondary volume {:.0}%",
+                player.secondary_volume()
+            ))
+        }
+        PlayerCommand::ToggleDucking => {
+            let enabled = !player.ducking();
+            player.set_ducking(enabled);
+            Some(format!("Ducking {}", on_off(enabled)))
+        }
+        PlayerCommand::CycleVisualization => player
+            .is_audio_only()
+            .then(|| format!("Visualization: {}", player.cycle_visualization().label())),
+        PlayerCommand::Picture(property, delta) => {
+            player.adjust_picture(property, delta);
+            // show the values being changed
+            *state.show_picture_panel.write() = true;
+            None
+        }
+        PlayerCommand::ResetPicture => {
+            player.reset_picture();
+            *state.show_picture_panel.write() = true;
+            None
+        }
+        PlayerCommand::TogglePicturePanel => {
+            let mut show = state.show_picture_panel.write();
+            *show = !*show;
+            None
+        }
+        PlayerCommand::CycleDeinterlace => (!player.is_audio_only())
+            .then(|| format!("Deinterlace: {}", player.cycle_deinterlace().label())),
+        PlayerCommand::CycleScaling if player.is_audio_only() => None,
+        PlayerCommand::CycleScaling => {
+            player.set_scaling(player.scaling().next());
+            Some(format!("Scaling: {}", player.scaling().label()))
+        }
+        PlayerCommand::ToggleShaders => Some(match player.toggle_shaders() {
+            Some(enabled) => format!("Shaders {}", on_off(enabled)),
+            None => "No shaders".to_string(),
+        }),
+        PlayerCommand::Ignore => None,
+    };
+    if let Some(message) = message {
+        info!("{}", message);
+        osd.show(message);
     }
 }
 
 #[tessera]
 #[shard]
-fn app(#[state] state: AppState, video_player_state: Arc<RwLock<VideoPlayerState>>) {
+fn app(
+    #[state] state: AppState,
+    video_player_state: Arc<RwLock<VideoPlayerState>>,
+    window: Arc<WindowControl>,
+    bindings: Arc<KeyBindings>,
+    osd: Arc<Osd>,
+) {
+    {
+        let state = state.clone();
+        let video_player_state = video_player_state.clone();
+        let window = window.clone();
+        let osd = osd.clone();
+        state_handler(Box::new(move |input| {
+            let cursor = input
+                .cursor_position_rel
+                .map(|position| (position.x.0, position.y.0));
+            state.controls_state.track_cursor(cursor);
+            if !input.cursor_events.is_empty() {
+                state.controls_state.wake();
+            }
+            for event in input.keyboard_events.iter() {
+                if event.state != ElementState::Pressed {
+                    continue;
+                }
+                if let Some(command) = bindings.command(&event.logical_key) {
+                    run_command(&state, &video_player_state, &window, &osd, command);
+                }
+            }
+        }));
+    }
+
+    let show_picture_panel = *state.show_picture_panel.read();
     background(move || {
-        operation_scrim(state.clone(), video_player_state.clone(), move || {
+        operation_scrim(
+            state.clone(),
+            video_player_state.clone(),
+            window,
+            move || {
+                boxed(
+                    BoxedArgs {
+                        alignment: Alignment::Center,
+                        width: DimensionValue::FILLED,
+                        height: DimensionValue::FILLED,
+                    },
+                    move |scope| {
+                        let video_player_state_clone = video_player_state.clone();
+                        scope.child(move || {
+                            video_player(
+                                VideoPlayerArgs {
+                                    width: DimensionValue::FILLED,
+                                    height: DimensionValue::FILLED,
+                                },
+                                video_player_state_clone,
+                            );
+                        });
+
+                        if show_picture_panel {
+                            let picture = video_player_state.read().picture();
+                            scope.child(move || {
+                                boxed(
+                                    BoxedArgs {
+                                        alignment: Alignment::CenterEnd,
+                                        width: DimensionValue::FILLED,
+                                        height: DimensionValue::FILLED,
+                                    },
+                                    move |scope| {
+                                        scope.child(move || picture_panel(picture));
+                                    },
+                                );
+                            });
+                        }
+
+                        scope.child(move || osd_layer(osd, video_player_state));
+                    },
+                );
+            },
+        );
+    });
+}
+
+#[tessera]
+fn picture_panel(picture: PictureAdjustments) {
+    let mut lines: Vec<String> = PictureProperty::ALL
+        .iter()
+        .map(|property| format!("{}: {}", property.label(), picture.get(*property)))
+        .collect();
+    lines.push("Backspace: reset".into());
+    let content = lines.join("\n");
+
+    fluid_glass(
+        FluidGlassArgs {
+            width: Dp(240.0).into(),
+            height: Dp(220.0).into(),
+            refraction_height: 50.0,
+            refraction_amount: 100.0,
+            blur_radius: 30.0,
+            shape: Shape::rounded_rectangle(Dp(25.0)),
+            tint_color: Color::WHITE.with_alpha(0.1),
+            ..Default::default()
+        },
+        None,
+        move || {
             boxed(
                 BoxedArgs {
                     alignment: Alignment::Center,
@@ -86,57 +576,18 @@ fn app(#[state] state: AppState, video_player_state: Arc<RwLock<VideoPlayerState
                     height: DimensionValue::FILLED,
                 },
                 move |scope| {
-                    let video_player_state_clone = video_player_state.clone();
                     scope.child(move || {
-                        video_player(
-                            VideoPlayerArgs {
-                                width: DimensionValue::FILLED,
-                                height: DimensionValue::FILLED,
-                            },
-                            video_player_state_clone,
-                        );
-                    });
-
-                    if !video_player_state.read().is_playing() {
-                        scope.child(|| {
-                            fluid_glass(
-                                FluidGlassArgs {
-                                    width: Dp(200.0).into(),
-                                    height: Dp(200.0).into(),
-                                    refraction_height: 50.0,
-                                    refraction_amount: 100.0,
-                                    blur_radius: 30.0,
-                                    shape: Shape::rounded_rectangle(Dp(25.0)),
-                                    tint_color: Color::WHITE.with_alpha(0.1),
-                                    ..Default::default()
-                                },
-                                None,
-                                || {
-                                    boxed(
-                                        BoxedArgs {
-                                            alignment: Alignment::Center,
-                                            width: DimensionValue::FILLED,
-                                            height: DimensionValue::FILLED,
-                                        },
-                                        |scope| {
-                                            scope.child(|| {
-                                                text(TextArgs {
-                                                    text: "Paused".into(),
-                                                    size: Dp(24.0),
-                                                    color: Color::WHITE,
-                                                    ..Default::default()
-                                                });
-                                            });
-                                        },
-                                    );
-                                },
-                            );
+                        text(TextArgs {
+                            text: content,
+                            size: Dp(18.0),
+                            color: Color::WHITE,
+                            ..Default::default()
                         });
-                    }
+                    });
                 },
             );
-        });
-    });
+        },
+    );
 }
 
 #[tessera]
@@ -159,9 +610,12 @@ fn background(child: impl FnOnce()) {
 fn operation_scrim(
     state: Arc<AppState>,
     video_player_state: Arc<RwLock<VideoPlayerState>>,
+    window: Arc<WindowControl>,
     child: impl FnOnce() + Send + Sync + 'static,
 ) {
     let scrim_ripple_state = state.scrim_ripple_state.clone();
+    let controls_state = state.controls_state.clone();
+    let controls_player = video_player_state.clone();
     boxed(
         BoxedArgs {
             alignment: Alignment::Center,
@@ -188,6 +642,20 @@ fn operation_scrim(
                     || {},
                 );
             });
+
+            // above the scrim, so clicks on the controls do not also pause
+            scope.child(move || {
+                boxed(
+                    BoxedArgs {
+                        alignment: Alignment::BottomCenter,
+                        width: DimensionValue::FILLED,
+                        height: DimensionValue::FILLED,
+                    },
+                    move |scope| {
+                        scope.child(move || control_bar(controls_state, controls_player, window));
+                    },
+                );
+            });
         },
     );
 }
@@ -1,39 +1,152 @@
 pub mod clock;
+pub mod cover;
 mod decoder;
+pub mod filter;
+pub mod info;
+pub mod picture;
 pub mod pipeline;
+pub mod scaling;
+pub mod seek_bar;
+mod shader;
+pub mod thumbnail;
+pub mod visualizer;
 
 use std::{
-    mem,
+    path::PathBuf,
     sync::{Arc, mpsc},
     thread,
     time::Duration,
 };
 
-use crate::audio::player as audio_player;
-use ffmpeg_next::{self as ffmpeg};
+use crate::{
+    audio::{
+        dsp::{DspSettings, Equalizer},
+        gain::MAX_VOLUME,
+        player as audio_player,
+        sink::{SinkKind, output_devices},
+        stats::AudioStats,
+        track::{self, AudioTrack},
+    },
+    config::SettingsFile,
+};
+use ffmpeg_next::{self as ffmpeg, util::frame::Video as FrameVideo};
 use parking_lot::RwLock;
 use tessera_ui::{ComputedData, Constraint, DimensionValue, tessera};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
+use self::{
+    clock::ClockMode,
+    cover::CoverArt,
+    filter::{DeinterlaceMode, VideoFilter},
+    info::{Chapter, MediaInfo},
+    picture::{PictureAdjustments, PictureProperty},
+    scaling::ScalingMode,
+    thumbnail::{Thumbnail, Thumbnailer},
+    visualizer::Visualization,
+};
+
+// frames this close before a seek target count as reaching it
+const SEEK_PRECISION: f64 = 0.001; // seconds
+// previous and next jump this far in files without chapters
+const SKIP_STEP: f64 = 10.0; // seconds
+// previous restarts the current chapter unless it started less than this long ago
+const RESTART_WINDOW: f64 = 3.0; // seconds
+// assumed frame spacing for frame stepping when the container states no frame rate
+const DEFAULT_FRAME_DURATION: f64 = 1.0 / 25.0; // seconds
+const MIN_SPEED: f64 = 0.25;
+const MAX_SPEED: f64 = 4.0;
+
+/// Playback speeds offered by the controls.
+pub const SPEEDS: [f64; 7] = [0.5, 0.75, 1.0, 1.25, 1.5, 1.75, 2.0];
+
 pub struct VideoPlayerArgs {
     pub width: DimensionValue,
     pub height: DimensionValue,
 }
 
+/// Options fixed at the time a file is opened.
+#[derive(Clone, Debug, Default)]
+pub struct VideoPlayerOptions {
+    pub clock_mode: ClockMode,
+    pub deinterlace: DeinterlaceMode,
+    /// libavfilter graph inserted between the decoder and the scaler, e.g. `hqdn3d`
+    pub video_filter: Option<String>,
+    /// WGSL post-processing passes, applied in order
+    pub shaders: Vec<PathBuf>,
+    pub scaling: ScalingMode,
+    /// Sharpening strength for [`ScalingMode::Cas`], in [0, 1]
+    pub sharpness: f32,
+    /// What to show in place of video for audio-only files
+    pub visualization: Visualization,
+    /// Separate audio file, such as a dub, offered as a track and selected at start
+    pub audio_file: Option<PathBuf>,
+    /// Delay of the tracks in `audio_file` in seconds, on top of the audio delay
+    pub audio_file_delay: f64,
+    /// Index into the track list of a track mixed over the main one, such as a commentary
+    pub secondary_track: Option<usize>,
+    pub audio: audio_player::AudioOptions,
+}
+
 enum DecodeThreadCommand {
     Exit,
+    SetDeinterlace(DeinterlaceMode),
+    // target in seconds and the serial tagging frames decoded from there
+    Seek(f64, u64),
 }
 
 pub struct VideoPlayerState {
     id: Uuid,
     width: u32,
     height: u32,
+    // sample aspect ratio of the video, 0/1 if unknown
+    aspect: ffmpeg::Rational,
+    // None for audio-only files, which have nothing to decode
     decode_thread: Option<thread::JoinHandle<()>>,
     sx_commander: mpsc::Sender<DecodeThreadCommand>,
-    rx_data: flume::Receiver<(Vec<u8>, f64)>,
+    // decoded frames waiting to be shown, tagged with their seek serial
+    rx_data: flume::Receiver<pipeline::VideoFrame>,
     playing: bool,
     clock: clock::GlobalClock,
-    #[allow(unused)]
+    info: MediaInfo,
+    // bumped on every seek so frames decoded before it are told apart
+    seek_serial: u64,
+    // pts of the frame on screen, written by the pipeline
+    shown_pts: Arc<RwLock<Option<f64>>>,
+    frame_duration: f64,
+    thumbnailer: Option<Thumbnailer>,
+    deinterlace: DeinterlaceMode,
+    // passes given on the command line, and those running now, empty while switched off
+    shader_list: Arc<[PathBuf]>,
+    shaders: Arc<[PathBuf]>,
+    picture: PictureAdjustments,
+    scaling: ScalingMode,
+    sharpness: f32,
+    // cover art and visualization shown instead of video for audio-only files
+    audio_only: bool,
+    // audio goes to a WAV file, whose timeline cannot follow a speed change
+    recording: bool,
+    cover: Option<Arc<CoverArt>>,
+    visualization: Visualization,
+    audio_delay: f64,
+    volume: f32,
+    muted: bool,
+    audio_device: Option<String>,
+    // embedded tracks followed by those of the external audio file
+    audio_tracks: Vec<AudioTrack>,
+    audio_track: Option<usize>,
+    // track mixed over the main one
+    secondary_track: Option<usize>,
+    main_volume: f32,
+    secondary_volume: f32,
+    ducking: bool,
+    dsp: DspSettings,
+    // index into Equalizer::PRESETS of the last preset picked with cycle_eq_preset
+    eq_preset: usize,
+    // per-file settings remembered between sessions
+    settings: SettingsFile,
+    // settings shared by all files, such as volume
+    global_settings: SettingsFile,
     audio_handle: audio_player::AudioHandle,
 }
 
@@ -42,83 +155,175 @@ impl Drop for VideoPlayerState {
         // clear buffered frames so the decoder thread can exit without blocking
         self.rx_data.drain();
         let _ = self.sx_commander.send(DecodeThreadCommand::Exit);
-        self.decode_thread.take().unwrap().join().unwrap();
+        if let Some(handle) = self.decode_thread.take() {
+            handle.join().unwrap();
+        }
     }
 }
 
 impl VideoPlayerState {
-    pub fn new(path: &str) -> Self {
+    pub fn new(path: &str, options: VideoPlayerOptions) -> Self {
         let (sx_commander, rx_commander) = mpsc::channel();
         // buffer up to 30 frames to smooth producer/consumer bursts
         let (sx_data, rx_data) = flume::bounded(30);
         let path = path.to_string();
         let decoder = decoder::VideoDecoder::new(&path);
-        let width = decoder.width();
-        let height = decoder.height();
-
-        let decode_thread = thread::spawn(move || {
-            let timebase_f64: f64 = decoder.time_base().into();
-            let mut scaler = ffmpeg::software::scaling::Context::get(
-                decoder.format(),
-                decoder.width(),
-                decoder.height(),
-                ffmpeg::format::Pixel::RGBA,
-                decoder.width(),
-                decoder.height(),
-                ffmpeg::software::scaling::Flags::BILINEAR,
-            )
-            .expect("Failed to create video scaler");
-            let mut scaled_frame = ffmpeg::util::frame::Video::empty();
-            for frame in decoder {
-                // poll exit command to allow responsive shutdown
-                while let Ok(cmd) = rx_commander.try_recv() {
-                    match cmd {
-                        DecodeThreadCommand::Exit => return,
-                    }
-                }
-                scaler
-                    .run(&frame, &mut scaled_frame)
-                    .expect("Failed to scale frame");
-                let mut data = scaled_frame.data(0).to_vec();
-
-                // convert frame pts to seconds for timing and scheduling
-                let mut pts_seconds = frame.pts().map(|p| p as f64 * timebase_f64).unwrap();
-                while let Err(e) = sx_data.send_timeout(
-                    (mem::take(&mut data), pts_seconds),
-                    Duration::from_millis(100),
-                ) {
-                    match e {
-                        flume::SendTimeoutError::Timeout((unsent_data, unsent_pts)) => {
-                            // check for exit to allow prompt shutdown; preserve pts and data for resend
-                            if let Ok(cmd) = rx_commander.try_recv() {
-                                match cmd {
-                                    DecodeThreadCommand::Exit => return,
-                                }
-                            }
-                            data = unsent_data;
-                            pts_seconds = unsent_pts;
-                        }
-                        flume::SendTimeoutError::Disconnected(_) => return,
-                    }
-                }
+        let audio_only = decoder.is_none();
+        let recording = matches!(options.audio.sink, SinkKind::Wav(_));
+        let info = MediaInfo::probe(&path);
+        let thumbnailer = info
+            .duration
+            .filter(|_| !audio_only)
+            .map(|duration| Thumbnailer::new(&path, duration));
+        let cover = if audio_only {
+            CoverArt::find(&path).map(Arc::new)
+        } else {
+            None
+        };
+        let frame_duration = decoder
+            .as_ref()
+            .and_then(|decoder| decoder.frame_duration())
+            .unwrap_or(DEFAULT_FRAME_DURATION);
+        let (width, height) = match (&decoder, &cover) {
+            (Some(decoder), _) => (decoder.width(), decoder.height()),
+            (None, Some(cover)) => (cover.width, cover.height),
+            (None, None) => (0, 0),
+        };
+        let aspect = decoder
+            .as_ref()
+            .map_or(ffmpeg::Rational::new(0, 1), |decoder| {
+                decoder.aspect_ratio()
+            });
+        let visualization = match options.visualization {
+            // nothing to show as cover art, so show the audio itself
+            Visualization::Cover if cover.is_none() => Visualization::Spectrum,
+            visualization => visualization,
+        };
+
+        let shaders: Arc<[PathBuf]> = options.shaders.into();
+        let settings = SettingsFile::for_media(&path);
+        let picture = PictureAdjustments::load(&settings);
+        let audio_delay = settings.get("audio_delay").unwrap_or(options.audio.delay);
+        let global_settings = SettingsFile::global();
+        let volume = global_settings
+            .get::<f32>("volume")
+            .unwrap_or(options.audio.volume)
+            .clamp(0.0, MAX_VOLUME);
+        let muted = options.audio.muted;
+        // an explicit --audio-device wins over the device picked in an earlier session
+        let audio_device = options
+            .audio
+            .device
+            .clone()
+            .or_else(|| global_settings.get("audio_device"));
+        let mut audio_tracks = track::probe_tracks(&path, 0.0, false);
+        let mut audio_track = track::default_track(&path, &audio_tracks);
+        if let Some(file) = &options.audio_file {
+            let external =
+                track::probe_tracks(&file.to_string_lossy(), options.audio_file_delay, true);
+            if external.is_empty() {
+                error!("no audio track found in '{}'", file.display());
+            } else {
+                // the external file is usually loaded because it is the wanted language
+                audio_track = Some(audio_tracks.len());
+            }
+            audio_tracks.extend(external);
+        }
+        let secondary_track = options.secondary_track.filter(|&index| {
+            let valid = index < audio_tracks.len() && Some(index) != audio_track;
+            if !valid {
+                error!("secondary audio track {} is not available", index + 1);
             }
+            valid
+        });
+        let main_volume = options.audio.main_volume.clamp(0.0, MAX_VOLUME);
+        let secondary_volume = options.audio.secondary_volume.clamp(0.0, MAX_VOLUME);
+        let ducking = options.audio.ducking;
+        let dsp = options.audio.dsp.clone();
+        let audio_options = audio_player::AudioOptions {
+            delay: audio_delay,
+            volume,
+            device: audio_device.clone(),
+            ..options.audio
+        };
+        let decode_thread = decoder.map(move |decoder| {
+            thread::spawn(move || {
+                let mut worker = VideoDecodeWorker {
+                    time_base: decoder.time_base(),
+                    deinterlace: options.deinterlace,
+                    user_filter: options.video_filter,
+                    filter: None,
+                    filter_dirty: true,
+                    deinterlacing: false,
+                    scaler: None,
+                    pending_seek: None,
+                    seek_target: None,
+                    serial: 0,
+                    sx_data,
+                    rx_commander,
+                };
+                worker.run(decoder);
+            })
         });
 
+        // there are no frames to follow without video, so audio has to drive the clock
+        let clock_mode = if audio_only && options.clock_mode == ClockMode::Video {
+            info!("no video stream, using the audio clock");
+            ClockMode::Audio
+        } else {
+            options.clock_mode
+        };
         // create shared clock so audio and video share the same timing reference
-        let clock = clock::GlobalClock::new();
+        let clock = clock::GlobalClock::with_mode(clock_mode);
 
         // spawn audio playback (best-effort)
-        let audio_handle = audio_player::spawn_audio(path.to_string(), clock.clone());
+        let audio_handle = audio_player::spawn_audio(
+            audio_track.map(|index| audio_tracks[index].clone()),
+            secondary_track.map(|index| audio_tracks[index].clone()),
+            clock.clone(),
+            audio_options,
+        );
+        audio_handle.pcm_tap().set_enabled(audio_only);
 
         Self {
             width,
             height,
+            aspect,
             id: Uuid::new_v4(),
-            decode_thread: Some(decode_thread),
+            decode_thread,
             sx_commander,
             rx_data,
             playing: true,
             clock,
+            info,
+            seek_serial: 0,
+            shown_pts: Default::default(),
+            frame_duration,
+            thumbnailer,
+            deinterlace: options.deinterlace,
+            shader_list: shaders.clone(),
+            shaders,
+            picture,
+            scaling: options.scaling,
+            sharpness: options.sharpness.clamp(0.0, 1.0),
+            audio_only,
+            recording,
+            cover,
+            visualization,
+            audio_delay,
+            volume,
+            muted,
+            audio_device,
+            audio_tracks,
+            audio_track,
+            secondary_track,
+            main_volume,
+            secondary_volume,
+            ducking,
+            dsp,
+            eq_preset: 0,
+            settings,
+            global_settings,
             audio_handle,
         }
     }
@@ -126,12 +331,14 @@ impl VideoPlayerState {
     pub fn pause(&mut self) {
         // pause shared clock to stop playback timing
         self.clock.pause();
+        self.audio_handle.set_paused(true);
         self.playing = false;
     }
 
     pub fn resume(&mut self) {
         // resume shared clock to continue playback timing
         self.clock.resume();
+        self.audio_handle.set_paused(false);
         self.playing = true;
     }
 
@@ -146,20 +353,691 @@ impl VideoPlayerState {
     pub fn is_playing(&self) -> bool {
         self.playing
     }
+
+    /// Current playback position in seconds.
+    pub fn position(&self) -> f64 {
+        let now = self.clock.now().max(0.0);
+        self.info.duration.map_or(now, |duration| now.min(duration))
+    }
+
+    /// Length of the file in seconds, if known.
+    pub fn duration(&self) -> Option<f64> {
+        self.info.duration
+    }
+
+    pub fn chapters(&self) -> &[Chapter] {
+        &self.info.chapters
+    }
+
+    /// The chapter playing at `seconds`.
+    pub fn chapter_at(&self, seconds: f64) -> Option<&Chapter> {
+        self.info.chapter_at(seconds)
+    }
+
+    /// Jumps to `seconds`; video resumes from the exact frame and audio restarts there.
+    pub fn seek(&mut self, seconds: f64) {
+        let seconds = match self.info.duration {
+            Some(duration) => seconds.clamp(0.0, duration),
+            None => seconds.max(0.0),
+        };
+        self.seek_serial += 1;
+        *self.shown_pts.write() = None;
+        self.clock.seek(seconds);
+        let _ = self
+            .sx_commander
+            .send(DecodeThreadCommand::Seek(seconds, self.seek_serial));
+        // frames queued from the old position would only be dropped by the pipeline
+        self.rx_data.drain();
+        self.audio_handle.seek();
+    }
+
+    /// Pauses and shows the next frame, or the previous one when `forward` is false.
+    pub fn step_frame(&mut self, forward: bool) {
+        if self.audio_only {
+            return;
+        }
+        self.pause();
+        // step from the frame on screen; the clock may be anywhere up to the next one
+        let current = self.shown_pts.read().unwrap_or_else(|| self.position());
+        let step = if forward {
+            self.frame_duration
+        } else {
+            -self.frame_duration
+        };
+        self.seek(current + step);
+    }
+
+    /// Jumps to the next chapter, or ahead by a few seconds in files without chapters.
+    pub fn next_chapter(&mut self) {
+        let position = self.position();
+        if self.info.chapters.is_empty() {
+            self.seek(position + SKIP_STEP);
+            return;
+        }
+        let next = self
+            .info
+            .chapters
+            .iter()
+            .find(|chapter| chapter.start > position + SEEK_PRECISION)
+            .map(|chapter| chapter.start);
+        if let Some(start) = next {
+            self.seek(start);
+        }
+    }
+
+    /// Jumps to the start of the current chapter, or of the previous one right after a chapter
+    /// starts; files without chapters go back by a few seconds.
+    pub fn previous_chapter(&mut self) {
+        let position = self.position();
+        if self.info.chapters.is_empty() {
+            self.seek(position - SKIP_STEP);
+            return;
+        }
+        let start = self
+            .info
+            .chapters
+            .iter()
+            .rev()
+            .map(|chapter| chapter.start)
+            .find(|&start| start < position - RESTART_WINDOW)
+            .unwrap_or(0.0);
+        self.seek(start);
+    }
+
+    /// Playback speed, where 1.0 is normal.
+    pub fn speed(&self) -> f64 {
+        self.clock.speed()
+    }
+
+    /// Plays faster or slower; audio keeps its pitch.
+    pub fn set_speed(&mut self, speed: f64) {
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        if speed == self.speed() {
+            return;
+        }
+        // the recording would fall out of step with the picture
+        if self.recording {
+            warn!("the speed cannot be changed while recording audio to a file");
+            return;
+        }
+        self.clock.set_speed(speed);
+        self.audio_handle.set_speed(speed);
+    }
+
+    /// Moves to the next faster or slower entry of [`SPEEDS`] and returns the new speed.
+    pub fn step_speed(&mut self, faster: bool) -> f64 {
+        let speed = self.speed();
+        let next = if faster {
+            SPEEDS.iter().find(|&&s| s > speed)
+        } else {
+            SPEEDS.iter().rev().find(|&&s| s < speed)
+        };
+        if let Some(&next) = next {
+            self.set_speed(next);
+        }
+        self.speed()
+    }
+
+    /// Preview picture for the seek bar at `seconds`, decoded in the background.
+    pub fn thumbnail(&self, seconds: f64) -> Option<Arc<Thumbnail>> {
+        self.thumbnailer.as_ref()?.thumbnail(seconds)
+    }
+
+    /// Switches deinterlacing mode; takes effect from the next decoded frame.
+    pub fn set_deinterlace(&mut self, mode: DeinterlaceMode) {
+        self.deinterlace = mode;
+        let _ = self
+            .sx_commander
+            .send(DecodeThreadCommand::SetDeinterlace(mode));
+    }
+
+    /// Switches to the next deinterlacing mode; returns the new one.
+    pub fn cycle_deinterlace(&mut self) -> DeinterlaceMode {
+        self.set_deinterlace(self.deinterlace.next());
+        self.deinterlace
+    }
+
+    /// Switches the `--shader` passes off or back on; `None` if there are none.
+    pub fn toggle_shaders(&mut self) -> Option<bool> {
+        if self.shader_list.is_empty() {
+            return None;
+        }
+        self.shaders = if self.shaders.is_empty() {
+            self.shader_list.clone()
+        } else {
+            Arc::from([])
+        };
+        Some(!self.shaders.is_empty())
+    }
+
+    pub fn scaling(&self) -> ScalingMode {
+        self.scaling
+    }
+
+    pub fn set_scaling(&mut self, scaling: ScalingMode) {
+        self.scaling = scaling;
+    }
+
+    pub fn picture(&self) -> PictureAdjustments {
+        self.picture
+    }
+
+    /// Nudges one picture adjustment by `delta` and remembers it for this file.
+    pub fn adjust_picture(&mut self, property: PictureProperty, delta: i32) {
+        let mut picture = self.picture;
+        picture.set(property, picture.get(property) + delta);
+        self.set_picture(picture);
+    }
+
+    /// Restores every picture adjustment to neutral.
+    pub fn reset_picture(&mut self) {
+        self.set_picture(PictureAdjustments::default());
+    }
+
+    fn set_picture(&mut self, picture: PictureAdjustments) {
+        if picture == self.picture {
+            return;
+        }
+        self.picture = picture;
+        picture.store(&mut self.settings);
+        self.settings.save();
+    }
+
+    /// Display size of the video, or of the cover art of audio-only files, if there is a
+    /// picture; non-square pixels widen or narrow the video, e.g. 720x480 at 32:27 is 853x480.
+    pub fn picture_size(&self) -> Option<(u32, u32)> {
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
+        let (num, den) = (self.aspect.numerator(), self.aspect.denominator());
+        let width = if num > 0 && den > 0 {
+            (self.width as u64 * num as u64 / den as u64).max(1) as u32
+        } else {
+            self.width
+        };
+        Some((width, self.height))
+    }
+
+    /// Whether the file has no video, so cover art or a visualization is shown instead.
+    pub fn is_audio_only(&self) -> bool {
+        self.audio_only
+    }
+
+    /// Switches to the next visualization, skipping cover art when the file has none.
+    pub fn cycle_visualization(&mut self) -> Visualization {
+        let mut next = self.visualization.next();
+        if next == Visualization::Cover && self.cover.is_none() {
+            next = next.next();
+        }
+        self.visualization = next;
+        next
+    }
+
+    /// Volume in percent, from 0 to [`MAX_VOLUME`].
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    /// Sets the volume in percent and remembers it for future sessions.
+    pub fn set_volume(&mut self, percent: f32) {
+        let percent = percent.clamp(0.0, MAX_VOLUME);
+        self.volume = percent;
+        self.audio_handle.set_volume(percent);
+        self.global_settings.set("volume", percent);
+        self.global_settings.save();
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.audio_handle.set_muted(muted);
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.set_muted(!self.muted);
+    }
+
+    /// Moves audio to another output device without interrupting playback, and remembers it
+    /// for future sessions.
+    pub fn set_audio_device(&mut self, name: Option<String>) {
+        if name == self.audio_device {
+            return;
+        }
+        match &name {
+            Some(name) => self.global_settings.set("audio_device", name),
+            None => self.global_settings.remove("audio_device"),
+        }
+        self.global_settings.save();
+        self.audio_device = name.clone();
+        self.audio_handle.set_device(name);
+    }
+
+    /// Switches to the next output device, going through the system default after the last one.
+    pub fn cycle_audio_device(&mut self) -> Option<String> {
+        let devices = output_devices();
+        let next = match &self.audio_device {
+            None => devices.first().cloned(),
+            Some(current) => devices
+                .iter()
+                .position(|name| name == current)
+                .and_then(|index| devices.get(index + 1))
+                .cloned(),
+        };
+        self.set_audio_device(next.clone());
+        next
+    }
+
+    /// Every playable audio track: embedded ones first, then those of `--audio-file`.
+    pub fn audio_tracks(&self) -> &[AudioTrack] {
+        &self.audio_tracks
+    }
+
+    /// Index into [`audio_tracks`](Self::audio_tracks) of the playing track.
+    pub fn audio_track(&self) -> Option<usize> {
+        self.audio_track
+    }
+
+    /// Switches audio to another track, continuing from the current position.
+    pub fn set_audio_track(&mut self, index: usize) {
+        let Some(track) = self.audio_tracks.get(index) else {
+            return;
+        };
+        if self.audio_track == Some(index) {
+            return;
+        }
+        self.audio_track = Some(index);
+        self.audio_handle.set_track(Some(track.clone()));
+        // mixing a track over itself only makes it louder
+        if self.secondary_track == Some(index) {
+            self.set_secondary_track(None);
+        }
+    }
+
+    /// Switches to the next audio track, wrapping around; returns the new index.
+    pub fn cycle_audio_track(&mut self) -> Option<usize> {
+        if self.audio_tracks.is_empty() {
+            return None;
+        }
+        let next = self
+            .audio_track
+            .map_or(0, |index| (index + 1) % self.audio_tracks.len());
+        self.set_audio_track(next);
+        self.audio_track
+    }
+
+    /// Mixes another track, such as a commentary, over the main one; `None` stops it.
+    pub fn set_secondary_track(&mut self, index: Option<usize>) {
+        let index = index.filter(|&index| index < self.audio_tracks.len());
+        if index == self.secondary_track {
+            return;
+        }
+        self.secondary_track = index;
+        self.audio_handle
+            .set_secondary_track(index.map(|index| self.audio_tracks[index].clone()));
+    }
+
+    /// Switches the secondary track to the next one other than the main track, then off.
+    pub fn cycle_secondary_track(&mut self) -> Option<usize> {
+        let start = self.secondary_track.map_or(0, |index| index + 1);
+        let next = (start..self.audio_tracks.len()).find(|&index| Some(index) != self.audio_track);
+        self.set_secondary_track(next);
+        next
+    }
+
+    /// Volume of the main track in percent, applied before it is mixed.
+    pub fn main_volume(&self) -> f32 {
+        self.main_volume
+    }
+
+    pub fn set_main_volume(&mut self, percent: f32) {
+        self.main_volume = percent.clamp(0.0, MAX_VOLUME);
+        self.audio_handle.set_main_volume(self.main_volume);
+    }
+
+    /// Volume of the secondary track in percent, applied before it is mixed.
+    pub fn secondary_volume(&self) -> f32 {
+        self.secondary_volume
+    }
+
+    pub fn set_secondary_volume(&mut self, percent: f32) {
+        self.secondary_volume = percent.clamp(0.0, MAX_VOLUME);
+        self.audio_handle
+            .set_secondary_volume(self.secondary_volume);
+    }
+
+    pub fn ducking(&self) -> bool {
+        self.ducking
+    }
+
+    /// Lowers the main track while the secondary one is active.
+    pub fn set_ducking(&mut self, enabled: bool) {
+        self.ducking = enabled;
+        self.audio_handle.set_ducking(enabled);
+    }
+
+    /// Audio buffering counters for diagnostics.
+    pub fn audio_stats(&self) -> AudioStats {
+        self.audio_handle.stats()
+    }
+
+    pub fn night_mode(&self) -> bool {
+        self.dsp.night_mode
+    }
+
+    /// Turns the dynamic range compressor for quiet listening on or off.
+    pub fn set_night_mode(&mut self, enabled: bool) {
+        self.dsp.night_mode = enabled;
+        self.audio_handle.set_dsp(self.dsp.clone());
+    }
+
+    pub fn set_equalizer(&mut self, equalizer: Equalizer) {
+        self.dsp.equalizer = equalizer;
+        self.audio_handle.set_dsp(self.dsp.clone());
+    }
+
+    /// Switches to the next equalizer preset and returns its name.
+    pub fn cycle_eq_preset(&mut self) -> &'static str {
+        self.eq_preset = (self.eq_preset + 1) % Equalizer::PRESETS.len();
+        let name = Equalizer::PRESETS[self.eq_preset];
+        self.set_equalizer(Equalizer::preset(name).unwrap());
+        name
+    }
+
+    /// Audio offset in seconds; positive values play audio later than video.
+    pub fn audio_delay(&self) -> f64 {
+        self.audio_delay
+    }
+
+    /// Shifts audio against video to fix badly muxed files, and remembers it for this file.
+    pub fn set_audio_delay(&mut self, seconds: f64) {
+        // round away float noise accumulated by repeated small steps
+        let seconds = (seconds * 1000.0).round() / 1000.0;
+        self.audio_delay = seconds;
+        self.audio_handle.set_delay(seconds);
+        self.store_delay("audio_delay", seconds);
+    }
+
+    fn store_delay(&mut self, key: &str, seconds: f64) {
+        if seconds == 0.0 {
+            self.settings.remove(key);
+        } else {
+            self.settings.set(key, seconds);
+        }
+        self.settings.save();
+    }
+}
+
+/// State owned by the video decode thread.
+struct VideoDecodeWorker {
+    time_base: ffmpeg::Rational,
+    deinterlace: DeinterlaceMode,
+    user_filter: Option<String>,
+    filter: Option<VideoFilter>,
+    // set when the filter graph must be rebuilt before the next frame
+    filter_dirty: bool,
+    // whether the current filter graph includes the deinterlacer
+    deinterlacing: bool,
+    scaler: Option<ffmpeg::software::scaling::Context>,
+    // seek requested but not carried out yet; frames still decoded are stale
+    pending_seek: Option<f64>,
+    // frames before this time are decoded only to reach the seek target
+    seek_target: Option<f64>,
+    serial: u64,
+    sx_data: flume::Sender<pipeline::VideoFrame>,
+    rx_commander: mpsc::Receiver<DecodeThreadCommand>,
+}
+
+impl VideoDecodeWorker {
+    fn run(&mut self, mut decoder: decoder::VideoDecoder) {
+        loop {
+            // poll commands to allow responsive shutdown and live reconfiguration
+            if !self.poll_commands() {
+                return;
+            }
+
+            if let Some(seconds) = self.pending_seek.take() {
+                if let Err(e) = decoder.seek(seconds) {
+                    error!("failed to seek video to {:.3}s: {:?}", seconds, e);
+                }
+                // frames held by the graph belong to the old position
+                self.filter = None;
+                self.filter_dirty = true;
+                self.seek_target = Some(seconds);
+            }
+
+            let Some(frame) = decoder.next() else {
+                if !self.finish() {
+                    return;
+                }
+                // stay alive at the end so playback can seek back
+                let Ok(cmd) = self.rx_commander.recv() else {
+                    return;
+                };
+                if !self.handle_command(cmd) {
+                    return;
+                }
+                continue;
+            };
+
+            let deinterlace = self.deinterlace.applies_to(&frame);
+            if self.filter_dirty || (deinterlace && !self.deinterlacing) {
+                self.filter_dirty = false;
+                self.filter = self.build_filter(&frame, deinterlace);
+            }
+
+            let Some(filter) = self.filter.as_mut() else {
+                if !self.present(&frame, self.time_base) {
+                    return;
+                }
+                continue;
+            };
+
+            if let Err(e) = filter.push(&frame) {
+                error!("failed to feed video filter: {:?}", e);
+                self.filter = None;
+                continue;
+            }
+            // double-rate output may yield two frames per input, so drain before decoding more
+            let time_base = filter.time_base();
+            let filtered: Vec<_> = std::iter::from_fn(|| filter.pull()).collect();
+            for frame in filtered {
+                if !self.present(&frame, time_base) {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Drains frames still held by the graph, e.g. the last fields of a deinterlacer; returns
+    /// false if the thread should exit.
+    fn finish(&mut self) -> bool {
+        if let Some(mut filter) = self.filter.take()
+            && filter.flush().is_ok()
+        {
+            let time_base = filter.time_base();
+            while let Some(frame) = filter.pull() {
+                if !self.present(&frame, time_base) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Builds the deinterlacer followed by the user filter chain, or None if neither applies.
+    fn build_filter(&mut self, frame: &FrameVideo, deinterlace: bool) -> Option<VideoFilter> {
+        self.deinterlacing = deinterlace;
+        let deinterlace_spec = if deinterlace {
+            self.deinterlace.filter_spec()
+        } else {
+            None
+        };
+        let spec = deinterlace_spec
+            .into_iter()
+            .chain(self.user_filter.clone())
+            .collect::<Vec<_>>()
+            .join(",");
+        if spec.is_empty() {
+            return None;
+        }
+
+        match VideoFilter::new(&spec, frame, self.time_base) {
+            Ok(filter) => Some(filter),
+            Err(e) => {
+                // drop the offending part rather than retry on every frame
+                error!("failed to create video filter '{}': {:?}", spec, e);
+                if self.user_filter.take().is_some() {
+                    self.filter_dirty = true;
+                } else {
+                    self.deinterlace = DeinterlaceMode::Off;
+                }
+                None
+            }
+        }
+    }
+
+    /// Handles pending commands; returns false if the thread should exit.
+    fn poll_commands(&mut self) -> bool {
+        while let Ok(cmd) = self.rx_commander.try_recv() {
+            if !self.handle_command(cmd) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Applies one command; returns false if the thread should exit.
+    fn handle_command(&mut self, cmd: DecodeThreadCommand) -> bool {
+        match cmd {
+            DecodeThreadCommand::Exit => return false,
+            DecodeThreadCommand::SetDeinterlace(mode) => {
+                self.deinterlace = mode;
+                // rebuild lazily so the new mode sees the next frame's flags
+                self.filter_dirty = true;
+            }
+            DecodeThreadCommand::Seek(seconds, serial) => {
+                self.pending_seek = Some(seconds);
+                self.serial = serial;
+            }
+        }
+        true
+    }
+
+    /// Converts a frame to RGBA and hands it to the pipeline; returns false if the thread should exit.
+    fn present(&mut self, frame: &FrameVideo, time_base: ffmpeg::Rational) -> bool {
+        // convert frame pts to seconds for timing and scheduling
+        let timebase_f64: f64 = time_base.into();
+        let pts_seconds = frame.pts().map(|p| p as f64 * timebase_f64).unwrap();
+        if self.pending_seek.is_some() {
+            return true;
+        }
+        if let Some(target) = self.seek_target {
+            // seeking lands on the keyframe before the target; skip up to the exact frame
+            if pts_seconds < target - SEEK_PRECISION {
+                return true;
+            }
+            self.seek_target = None;
+        }
+
+        // filters may change the pixel format and size, so recreate the scaler when the input
+        // shape changes; the output keeps the filtered size so a crop is not stretched back
+        let stale = self.scaler.as_ref().is_none_or(|s| {
+            let input = s.input();
+            input.format != frame.format()
+                || input.width != frame.width()
+                || input.height != frame.height()
+        });
+        if stale {
+            self.scaler = Some(
+                ffmpeg::software::scaling::Context::get(
+                    frame.format(),
+                    frame.width(),
+                    frame.height(),
+                    ffmpeg::format::Pixel::RGBA,
+                    frame.width(),
+                    frame.height(),
+                    ffmpeg::software::scaling::Flags::BILINEAR,
+                )
+                .expect("Failed to create video scaler"),
+            );
+        }
+        let mut scaled_frame = FrameVideo::empty();
+        self.scaler
+            .as_mut()
+            .unwrap()
+            .run(frame, &mut scaled_frame)
+            .expect("Failed to scale frame");
+        let (width, height) = (scaled_frame.width(), scaled_frame.height());
+        // rows may be padded for alignment; the pipeline expects them tightly packed
+        let row = 4 * width as usize;
+        let rgba = scaled_frame
+            .data(0)
+            .chunks(scaled_frame.stride(0))
+            .take(height as usize)
+            .flat_map(|line| &line[..row])
+            .copied()
+            .collect();
+        let mut frame = pipeline::VideoFrame {
+            rgba,
+            width,
+            height,
+            pts: pts_seconds,
+            serial: self.serial,
+        };
+
+        while let Err(e) = self.sx_data.send_timeout(frame, Duration::from_millis(100)) {
+            match e {
+                flume::SendTimeoutError::Timeout(unsent) => {
+                    // check for commands to allow prompt shutdown; preserve pts and data for resend
+                    if !self.poll_commands() {
+                        return false;
+                    }
+                    // a seek makes the frame stale; the run loop carries it out next
+                    if self.pending_seek.is_some() {
+                        return true;
+                    }
+                    frame = unsent;
+                }
+                flume::SendTimeoutError::Disconnected(_) => return false,
+            }
+        }
+        true
+    }
 }
 
 #[tessera]
 pub fn video_player(args: VideoPlayerArgs, state: Arc<RwLock<VideoPlayerState>>) {
     measure(Box::new(move |input| {
-        input
-            .metadata_mut()
-            .push_draw_command(pipeline::VideoCommand {
-                id: state.read().id,
-                width: state.read().width,
-                height: state.read().height,
-                receiver: state.read().rx_data.clone(),
-                clock: state.read().clock.clone(),
-            });
+        if state.read().audio_only {
+            input
+                .metadata_mut()
+                .push_draw_command(visualizer::VisualizerCommand {
+                    id: state.read().id,
+                    tap: state.read().audio_handle.pcm_tap(),
+                    clock: state.read().clock.clone(),
+                    cover: state.read().cover.clone(),
+                    visualization: state.read().visualization,
+                });
+        } else {
+            input
+                .metadata_mut()
+                .push_draw_command(pipeline::VideoCommand {
+                    id: state.read().id,
+                    width: state.read().width,
+                    height: state.read().height,
+                    receiver: state.read().rx_data.clone(),
+                    serial: state.read().seek_serial,
+                    shown_pts: state.read().shown_pts.clone(),
+                    clock: state.read().clock.clone(),
+                    shaders: state.read().shaders.clone(),
+                    picture: state.read().picture,
+                    scaling: state.read().scaling,
+                    sharpness: state.read().sharpness,
+                });
+        }
         let size = Constraint::new(args.width, args.height).merge(input.parent_constraint);
         Ok(ComputedData {
             width: size.width.get_max().unwrap(),
@@ -2,11 +2,31 @@ use parking_lot::RwLock;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+// drift beyond this is treated as a discontinuity and corrected immediately
+const RESYNC_THRESHOLD: f64 = 0.1; // seconds
+// fraction of small drift corrected per sync, so callback jitter does not shake video timing
+const SLEW_FACTOR: f64 = 0.1;
+
+/// Which playback component drives the shared clock.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ClockMode {
+    /// Follow the samples actually played by the audio device
+    #[default]
+    Audio,
+    /// Follow presented video frames; the clock waits for late video instead of dropping it
+    Video,
+    /// Free-running wall clock
+    External,
+}
+
 #[derive(Debug)]
 struct Inner {
     offset_seconds: f64,
     last_instant: Instant,
     paused: bool,
+    mode: ClockMode,
+    // media seconds per wall-clock second
+    speed: f64,
 }
 
 /// GlobalClock provides a shared playback clock in seconds.
@@ -17,11 +37,17 @@ pub struct GlobalClock {
 
 impl GlobalClock {
     pub fn new() -> Self {
+        Self::with_mode(ClockMode::default())
+    }
+
+    pub fn with_mode(mode: ClockMode) -> Self {
         Self {
             inner: Arc::new(RwLock::new(Inner {
                 offset_seconds: 0.0,
                 last_instant: Instant::now(),
                 paused: false,
+                mode,
+                speed: 1.0,
             })),
         }
     }
@@ -33,7 +59,7 @@ impl GlobalClock {
             i.offset_seconds
         } else {
             let elapsed = Instant::now().duration_since(i.last_instant);
-            i.offset_seconds + duration_to_secs(elapsed)
+            i.offset_seconds + duration_to_secs(elapsed) * i.speed
         }
     }
 
@@ -42,7 +68,7 @@ impl GlobalClock {
         if !i.paused {
             // Update offset to current now
             let elapsed = Instant::now().duration_since(i.last_instant);
-            i.offset_seconds += duration_to_secs(elapsed);
+            i.offset_seconds += duration_to_secs(elapsed) * i.speed;
             i.paused = true;
         }
     }
@@ -59,6 +85,54 @@ impl GlobalClock {
             i.paused = false;
         }
     }
+
+    /// Jumps to media time `seconds`, keeping the paused state.
+    pub fn seek(&self, seconds: f64) {
+        let mut i = self.inner.write();
+        i.offset_seconds = seconds;
+        i.last_instant = Instant::now();
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.inner.read().speed
+    }
+
+    /// Changes how fast media time advances, continuing from the current position.
+    pub fn set_speed(&self, speed: f64) {
+        let mut i = self.inner.write();
+        if !i.paused {
+            let now = Instant::now();
+            i.offset_seconds += duration_to_secs(now.duration_since(i.last_instant)) * i.speed;
+            i.last_instant = now;
+        }
+        i.speed = speed;
+    }
+
+    pub fn mode(&self) -> ClockMode {
+        self.inner.read().mode
+    }
+
+    /// Reports that media time `pts` is being presented right now by `source`.
+    ///
+    /// Ignored unless `source` is the current master. Large drift is corrected at once,
+    /// small drift is slewed so the clock stays monotonic-looking for video scheduling.
+    pub fn sync(&self, source: ClockMode, pts: f64) {
+        let mut i = self.inner.write();
+        if i.paused || i.mode != source {
+            return;
+        }
+        let now = Instant::now();
+        let current =
+            i.offset_seconds + duration_to_secs(now.duration_since(i.last_instant)) * i.speed;
+        let drift = pts - current;
+        let correction = if drift.abs() > RESYNC_THRESHOLD {
+            drift
+        } else {
+            drift * SLEW_FACTOR
+        };
+        i.offset_seconds = current + correction;
+        i.last_instant = now;
+    }
 }
 
 fn duration_to_secs(d: Duration) -> f64 {
@@ -0,0 +1,129 @@
+use std::{fs, path::Path};
+
+use ffmpeg_next::{self as ffmpeg, util::frame::Video as FrameVideo};
+use tracing::{info, warn};
+
+/// Image files looked for next to the audio file when it has no embedded picture, in order
+/// of preference and matched case-insensitively.
+const FOLDER_IMAGES: [&str; 6] = [
+    "folder.jpg",
+    "folder.png",
+    "cover.jpg",
+    "cover.png",
+    "front.jpg",
+    "front.png",
+];
+// larger pictures are scaled down when loaded; the view is rarely bigger than this
+const MAX_SIZE: u32 = 1024; // pixels
+
+/// Album art decoded to tightly packed RGBA.
+pub struct CoverArt {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl CoverArt {
+    /// The picture embedded in `path` (attached_pic), or else an image such as folder.jpg in
+    /// the same directory.
+    pub fn find(path: &str) -> Option<Self> {
+        let cover = Self::embedded(path).or_else(|| Self::from_folder(path));
+        if let Some(cover) = &cover {
+            info!("showing {}x{} cover art", cover.width, cover.height);
+        }
+        cover
+    }
+
+    fn embedded(path: &str) -> Option<Self> {
+        let mut ictx = ffmpeg::format::input(path).ok()?;
+        let index = ictx
+            .streams()
+            .find(|stream| {
+                stream
+                    .disposition()
+                    .contains(ffmpeg::format::stream::Disposition::ATTACHED_PIC)
+            })?
+            .index();
+        decode_picture(&mut ictx, index)
+    }
+
+    fn from_folder(path: &str) -> Option<Self> {
+        let dir = Path::new(path).parent()?;
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let image = fs::read_dir(dir)
+            .ok()?
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_lowercase();
+                let rank = FOLDER_IMAGES.iter().position(|&image| image == name)?;
+                Some((rank, entry.path()))
+            })
+            .min_by_key(|&(rank, _)| rank)?
+            .1;
+
+        let mut ictx = ffmpeg::format::input(&image).ok()?;
+        let index = ictx.streams().best(ffmpeg::media::Type::Video)?.index();
+        decode_picture(&mut ictx, index)
+    }
+}
+
+/// Decodes the first picture of stream `index` and converts it to RGBA.
+fn decode_picture(ictx: &mut ffmpeg::format::context::Input, index: usize) -> Option<CoverArt> {
+    let parameters = ictx.stream(index)?.parameters();
+    let mut decoder = ffmpeg::codec::context::Context::from_parameters(parameters)
+        .ok()?
+        .decoder()
+        .video()
+        .ok()?;
+
+    // for attached pictures the whole image is a single packet queued ahead of the audio
+    let packet = ictx
+        .packets()
+        .find(|(stream, _)| stream.index() == index)
+        .map(|(_, packet)| packet)?;
+    let mut frame = FrameVideo::empty();
+    let decoded = decoder
+        .send_packet(&packet)
+        .and_then(|()| decoder.send_eof())
+        .and_then(|()| decoder.receive_frame(&mut frame));
+    if let Err(e) = decoded {
+        warn!("failed to decode cover art: {:?}", e);
+        return None;
+    }
+
+    let scale = (MAX_SIZE as f64 / frame.width().max(frame.height()) as f64).min(1.0);
+    let width = ((frame.width() as f64 * scale) as u32).max(1);
+    let height = ((frame.height() as f64 * scale) as u32).max(1);
+    let mut scaler = ffmpeg::software::scaling::Context::get(
+        frame.format(),
+        frame.width(),
+        frame.height(),
+        ffmpeg::format::Pixel::RGBA,
+        width,
+        height,
+        ffmpeg::software::scaling::Flags::BICUBIC,
+    )
+    .ok()?;
+    let mut rgba_frame = FrameVideo::empty();
+    scaler.run(&frame, &mut rgba_frame).ok()?;
+
+    // rows may be padded for alignment, so copy them one by one
+    let stride = rgba_frame.stride(0);
+    let row = width as usize * 4;
+    let rgba = rgba_frame
+        .data(0)
+        .chunks(stride)
+        .take(height as usize)
+        .flat_map(|line| &line[..row])
+        .copied()
+        .collect();
+    Some(CoverArt {
+        width,
+        height,
+        rgba,
+    })
+}
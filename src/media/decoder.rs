@@ -7,20 +7,30 @@ pub struct VideoDecoder {
     decoder: ffmpeg::codec::decoder::Video,
     sent_eof: bool,
     time_base: ffmpeg::Rational,
+    frame_rate: ffmpeg::Rational,
 }
 
 impl VideoDecoder {
-    pub fn new(path: &str) -> Self {
+    /// Opens the main video stream of `path`; `None` if there is only audio and cover art.
+    pub fn new(path: &str) -> Option<Self> {
         // open input file; panic on failure because caller cannot recover at this layer
         let ictx = ffmpeg::format::input(path).expect("Failed to open input file");
 
-        // ensure a video stream exists; panic if missing because higher layers cannot recover
-        let stream = ictx
-            .streams()
-            .best(ffmpeg::media::Type::Video)
-            .expect("no video stream found in input (unsupported format)");
+        // cover art is stored as a one-picture video stream and is shown by the audio view
+        let is_cover = |stream: &ffmpeg::format::stream::Stream| {
+            stream
+                .disposition()
+                .contains(ffmpeg::format::stream::Disposition::ATTACHED_PIC)
+        };
+        let stream = match ictx.streams().best(ffmpeg::media::Type::Video) {
+            Some(stream) if !is_cover(&stream) => stream,
+            _ => ictx.streams().find(|stream| {
+                stream.parameters().medium() == ffmpeg::media::Type::Video && !is_cover(stream)
+            })?,
+        };
         let stream_index = stream.index();
         let time_base = stream.time_base();
+        let frame_rate = stream.avg_frame_rate();
 
         // build codec context; panic on invalid stream parameters since this indicates unrecoverable input
         let context_decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())
@@ -32,13 +42,14 @@ impl VideoDecoder {
             .video()
             .expect("failed to obtain video decoder (unsupported codec)");
 
-        VideoDecoder {
+        Some(VideoDecoder {
             ictx,
             stream_index,
             decoder,
             sent_eof: false,
             time_base,
-        }
+            frame_rate,
+        })
     }
 
     pub fn width(&self) -> u32 {
@@ -49,13 +60,29 @@ impl VideoDecoder {
         self.decoder.height()
     }
 
-    pub fn format(&self) -> ffmpeg::format::Pixel {
-        self.decoder.format()
+    /// Shape of the stored pixels; 0/1 when the stream does not state it.
+    pub fn aspect_ratio(&self) -> ffmpeg::Rational {
+        self.decoder.aspect_ratio()
     }
 
     pub fn time_base(&self) -> ffmpeg::Rational {
         self.time_base
     }
+
+    /// Average time between frames in seconds, if the container states a frame rate.
+    pub fn frame_duration(&self) -> Option<f64> {
+        (self.frame_rate.numerator() > 0 && self.frame_rate.denominator() > 0)
+            .then(|| 1.0 / f64::from(self.frame_rate))
+    }
+
+    /// Moves to the keyframe at or before `seconds`; frames up to `seconds` still follow.
+    pub fn seek(&mut self, seconds: f64) -> Result<(), ffmpeg::Error> {
+        let ts = (seconds / f64::from(ffmpeg::rescale::TIME_BASE)) as i64;
+        self.ictx.seek(ts, ..ts)?;
+        self.decoder.flush();
+        self.sent_eof = false;
+        Ok(())
+    }
 }
 
 impl Iterator for VideoDecoder {
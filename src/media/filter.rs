@@ -0,0 +1,146 @@
+use ffmpeg_next::{self as ffmpeg, util::frame::Video as FrameVideo};
+use tracing::error;
+
+/// Controls whether the video decode thread runs frames through a deinterlacer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DeinterlaceMode {
+    /// Deinterlace once the decoder flags a frame as interlaced
+    #[default]
+    Auto,
+    /// Always deinterlace, regardless of frame flags
+    On,
+    /// Never deinterlace
+    Off,
+}
+
+impl DeinterlaceMode {
+    /// Returns true if a deinterlacer should be inserted for this frame.
+    pub fn applies_to(self, frame: &FrameVideo) -> bool {
+        match self {
+            DeinterlaceMode::Auto => frame.is_interlaced(),
+            DeinterlaceMode::On => true,
+            DeinterlaceMode::Off => false,
+        }
+    }
+
+    /// Builds the filtergraph description for this mode.
+    ///
+    /// Output is double-rate (one frame per field) so motion stays as smooth as the source.
+    pub fn filter_spec(self) -> Option<String> {
+        // only deinterlace flagged frames in auto mode so progressive segments pass through untouched
+        let deint = match self {
+            DeinterlaceMode::Auto => "interlaced",
+            DeinterlaceMode::On => "all",
+            DeinterlaceMode::Off => return None,
+        };
+        // prefer bwdif for quality; fall back to yadif on builds that lack it
+        let name = if ffmpeg::filter::find("bwdif").is_some() {
+            "bwdif"
+        } else {
+            "yadif"
+        };
+        Some(format!("{name}=mode=send_field:parity=auto:deint={deint}"))
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DeinterlaceMode::Auto => "auto",
+            DeinterlaceMode::On => "on",
+            DeinterlaceMode::Off => "off",
+        }
+    }
+
+    /// Auto, then on, then off, wrapping around.
+    pub fn next(self) -> Self {
+        match self {
+            DeinterlaceMode::Auto => DeinterlaceMode::On,
+            DeinterlaceMode::On => DeinterlaceMode::Off,
+            DeinterlaceMode::Off => DeinterlaceMode::Auto,
+        }
+    }
+}
+
+/// A libavfilter graph with a single video input and output.
+pub struct VideoFilter {
+    graph: ffmpeg::filter::Graph,
+    time_base: ffmpeg::Rational,
+}
+
+impl VideoFilter {
+    /// Builds a filter graph for frames shaped like `frame`, whose timestamps are in `time_base`.
+    pub fn new(
+        spec: &str,
+        frame: &FrameVideo,
+        time_base: ffmpeg::Rational,
+    ) -> Result<Self, ffmpeg::Error> {
+        let mut graph = ffmpeg::filter::Graph::new();
+
+        // an unknown sample aspect ratio is reported as 0/1, which buffersrc rejects
+        let aspect = frame.aspect_ratio();
+        let aspect = if aspect.numerator() > 0 && aspect.denominator() > 0 {
+            aspect
+        } else {
+            ffmpeg::Rational::new(1, 1)
+        };
+        let pix_fmt = frame
+            .format()
+            .descriptor()
+            .map(|d| d.name())
+            .ok_or(ffmpeg::Error::InvalidData)?;
+        let args = format!(
+            "video_size={}x{}:pix_fmt={}:time_base={}:pixel_aspect={}",
+            frame.width(),
+            frame.height(),
+            pix_fmt,
+            time_base,
+            aspect,
+        );
+
+        graph.add(
+            &ffmpeg::filter::find("buffer").ok_or(ffmpeg::Error::FilterNotFound)?,
+            "in",
+            &args,
+        )?;
+        graph.add(
+            &ffmpeg::filter::find("buffersink").ok_or(ffmpeg::Error::FilterNotFound)?,
+            "out",
+            "",
+        )?;
+        graph.output("in", 0)?.input("out", 0)?.parse(spec)?;
+        graph.validate()?;
+
+        // filters such as double-rate deinterlacers change the output time base
+        let time_base = graph.get("out").unwrap().sink().time_base();
+
+        Ok(Self { graph, time_base })
+    }
+
+    /// Time base of frames returned by [`VideoFilter::pull`].
+    pub fn time_base(&self) -> ffmpeg::Rational {
+        self.time_base
+    }
+
+    /// Feeds a decoded frame into the graph.
+    pub fn push(&mut self, frame: &FrameVideo) -> Result<(), ffmpeg::Error> {
+        self.graph.get("in").unwrap().source().add(frame)
+    }
+
+    /// Signals end of stream so buffered frames can be drained.
+    pub fn flush(&mut self) -> Result<(), ffmpeg::Error> {
+        self.graph.get("in").unwrap().source().flush()
+    }
+
+    /// Returns the next filtered frame, or None if the graph needs more input.
+    pub fn pull(&mut self) -> Option<FrameVideo> {
+        let mut filtered = FrameVideo::empty();
+        match self.graph.get("out").unwrap().sink().frame(&mut filtered) {
+            Ok(()) => Some(filtered),
+            Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::sys::EAGAIN => None,
+            Err(ffmpeg::Error::Eof) => None,
+            Err(e) => {
+                error!("video filter error: {:?}", e);
+                None
+            }
+        }
+    }
+}
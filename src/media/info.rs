@@ -0,0 +1,56 @@
+use ffmpeg_next as ffmpeg;
+use tracing::warn;
+
+/// A named section of the file, such as a movie scene or an audiobook chapter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chapter {
+    /// Start and end in seconds
+    pub start: f64,
+    pub end: f64,
+    pub title: Option<String>,
+}
+
+/// Container-level facts shown by the seek bar.
+#[derive(Clone, Debug, Default)]
+pub struct MediaInfo {
+    /// Length in seconds, if the container knows it
+    pub duration: Option<f64>,
+    /// Chapters in file order
+    pub chapters: Vec<Chapter>,
+}
+
+impl MediaInfo {
+    pub fn probe(path: &str) -> Self {
+        let ictx = match ffmpeg::format::input(path) {
+            Ok(ictx) => ictx,
+            Err(e) => {
+                warn!("failed to read media info of '{}': {:?}", path, e);
+                return Self::default();
+            }
+        };
+        // AV_NOPTS_VALUE and zero both mean the length is unknown, e.g. for live streams
+        let duration = Some(ictx.duration())
+            .filter(|&duration| duration > 0)
+            .map(|duration| duration as f64 * f64::from(ffmpeg::rescale::TIME_BASE));
+        let chapters = ictx
+            .chapters()
+            .map(|chapter| {
+                let time_base = f64::from(chapter.time_base());
+                Chapter {
+                    start: chapter.start() as f64 * time_base,
+                    end: chapter.end() as f64 * time_base,
+                    title: chapter.metadata().get("title").map(str::to_string),
+                }
+            })
+            .collect();
+        Self { duration, chapters }
+    }
+
+    /// The chapter playing at `seconds`.
+    pub fn chapter_at(&self, seconds: f64) -> Option<&Chapter> {
+        self.chapters
+            .iter()
+            .rev()
+            .find(|chapter| chapter.start <= seconds)
+    }
+}
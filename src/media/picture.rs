@@ -0,0 +1,118 @@
+use std::f32::consts::PI;
+
+use glam::Vec4;
+
+use crate::config::SettingsFile;
+
+/// A live picture adjustment applied by the display shader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PictureProperty {
+    Brightness,
+    Contrast,
+    Saturation,
+    Gamma,
+    Hue,
+}
+
+impl PictureProperty {
+    pub const ALL: [PictureProperty; 5] = [
+        PictureProperty::Brightness,
+        PictureProperty::Contrast,
+        PictureProperty::Saturation,
+        PictureProperty::Gamma,
+        PictureProperty::Hue,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PictureProperty::Brightness => "Brightness",
+            PictureProperty::Contrast => "Contrast",
+            PictureProperty::Saturation => "Saturation",
+            PictureProperty::Gamma => "Gamma",
+            PictureProperty::Hue => "Hue",
+        }
+    }
+
+    /// Name used in settings and key bindings.
+    pub fn key(self) -> &'static str {
+        match self {
+            PictureProperty::Brightness => "brightness",
+            PictureProperty::Contrast => "contrast",
+            PictureProperty::Saturation => "saturation",
+            PictureProperty::Gamma => "gamma",
+            PictureProperty::Hue => "hue",
+        }
+    }
+}
+
+/// Picture adjustments in the range -100..=100, where 0 leaves the image untouched.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PictureAdjustments {
+    pub brightness: i32,
+    pub contrast: i32,
+    pub saturation: i32,
+    pub gamma: i32,
+    pub hue: i32,
+}
+
+impl PictureAdjustments {
+    pub const MIN: i32 = -100;
+    pub const MAX: i32 = 100;
+
+    pub fn get(&self, property: PictureProperty) -> i32 {
+        match property {
+            PictureProperty::Brightness => self.brightness,
+            PictureProperty::Contrast => self.contrast,
+            PictureProperty::Saturation => self.saturation,
+            PictureProperty::Gamma => self.gamma,
+            PictureProperty::Hue => self.hue,
+        }
+    }
+
+    pub fn set(&mut self, property: PictureProperty, value: i32) {
+        let value = value.clamp(Self::MIN, Self::MAX);
+        match property {
+            PictureProperty::Brightness => self.brightness = value,
+            PictureProperty::Contrast => self.contrast = value,
+            PictureProperty::Saturation => self.saturation = value,
+            PictureProperty::Gamma => self.gamma = value,
+            PictureProperty::Hue => self.hue = value,
+        }
+    }
+
+    pub fn load(settings: &SettingsFile) -> Self {
+        let mut picture = Self::default();
+        for property in PictureProperty::ALL {
+            if let Some(value) = settings.get(property.key()) {
+                picture.set(property, value);
+            }
+        }
+        picture
+    }
+
+    pub fn store(&self, settings: &mut SettingsFile) {
+        for property in PictureProperty::ALL {
+            match self.get(property) {
+                // keep files small by only recording what differs from the default
+                0 => settings.remove(property.key()),
+                value => settings.set(property.key(), value),
+            }
+        }
+    }
+
+    /// Shader parameters: brightness offset, contrast, saturation and gamma factors.
+    pub fn factors(&self) -> Vec4 {
+        Vec4::new(
+            self.brightness as f32 / 100.0,
+            1.0 + self.contrast as f32 / 100.0,
+            1.0 + self.saturation as f32 / 100.0,
+            // exponential so that -100 and 100 are symmetric around neutral, as in mpv
+            (8.0_f32.ln() * self.gamma as f32 / 100.0).exp(),
+        )
+    }
+
+    /// Hue rotation in radians.
+    pub fn hue_radians(&self) -> f32 {
+        self.hue as f32 / 100.0 * PI
+    }
+}
@@ -1,67 +1,132 @@
-use std::{collections::HashMap, time::Instant};
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Instant};
 
-use crate::media::clock::GlobalClock;
+use crate::media::{
+    clock::{ClockMode, GlobalClock},
+    picture::PictureAdjustments,
+    scaling::ScalingMode,
+    shader::ShaderChain,
+};
 use encase::{ShaderType, UniformBuffer};
-use glam::Vec4;
+use glam::{Vec2, Vec4};
+use parking_lot::RwLock;
 use tessera_ui::{DrawCommand, DrawablePipeline, wgpu};
 use uuid::Uuid;
 
 #[derive(ShaderType)]
 struct VideoUniforms {
-    rect: Vec4, // x, y, w, h (normalized device coords or screen-normalized)
+    rect: Vec4,        // x, y, w, h (normalized device coords or screen-normalized)
+    picture: Vec4,     // brightness offset, contrast, saturation and gamma factors
+    source_size: Vec2, // video texture size in pixels
+    output_size: Vec2, // on-screen size in pixels, used to pick the kernel footprint
+    hue: f32,          // hue rotation in radians
+    sharpness: f32,    // CAS strength in [0, 1]
+}
+
+/// A decoded frame converted to tightly packed RGBA.
+pub struct VideoFrame {
+    pub rgba: Vec<u8>,
+    /// Size after filtering, which may differ from the stream size, e.g. after `crop`
+    pub width: u32,
+    pub height: u32,
+    pub pts: f64,
+    /// Seek serial the frame was decoded under
+    pub serial: u64,
 }
 
 struct VideoResources {
     uniform_buffer: wgpu::Buffer,
     bind_group: wgpu::BindGroup,
-    #[allow(unused)]
     bind_group_layout: wgpu::BindGroupLayout,
     texture_view: wgpu::TextureView,
-    #[allow(unused)]
     sampler: wgpu::Sampler,
-    pipeline: wgpu::RenderPipeline,
+    // one display pipeline per scaling kernel, indexed like `ScalingMode::ALL`
+    pipelines: Vec<wgpu::RenderPipeline>,
+}
+
+impl VideoResources {
+    /// Binds `texture_view` as the source sampled by the display pass.
+    fn create_bind_group(
+        gpu: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        texture_view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        gpu.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("video bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Creates the texture video frames of `width`x`height` are uploaded to.
+    fn create_texture(gpu: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+        let texture = gpu.create_texture(&wgpu::TextureDescriptor {
+            label: Some("video texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
 }
 
 struct VideoTarget {
     pub resources: VideoResources,
-    pub receiver: flume::Receiver<(Vec<u8>, f64)>,
+    pub receiver: flume::Receiver<VideoFrame>,
+    // seek serial of the frames being shown; older frames are discarded
+    serial: u64,
     updated: bool,
     // scheduling driven by presentation timestamps (PTS)
     pub first_pts: Option<f64>,
     pub first_instant: Option<Instant>,
     pub last_pts_seconds: Option<f64>,
+    // spacing between the last two presented frames, used to tighten scheduling tolerance
+    pub frame_interval: Option<f64>,
+    // pts of the frame on screen, shared with the player for frame stepping
+    shown_pts: Arc<RwLock<Option<f64>>>,
     // per-target clock for independent timing/control
     pub clock: GlobalClock,
     // single-frame slot to avoid pipeline-side buffering
-    pub next_frame_slot: Option<(Vec<u8>, f64)>,
+    pub next_frame_slot: Option<VideoFrame>,
+    // user post-processing passes and the bind group that displays their output
+    shader_chain: Option<ShaderChain>,
+    shader_bind_group: Option<wgpu::BindGroup>,
 }
 
 impl VideoTarget {
     fn new(
         gpu: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
-        receiver: flume::Receiver<(Vec<u8>, f64)>,
+        receiver: flume::Receiver<VideoFrame>,
         sample_count: u32,
         width: u32,
         height: u32,
         clock: GlobalClock,
     ) -> Self {
         // create texture used as the video render target
-        let texture = gpu.create_texture(&wgpu::TextureDescriptor {
-            label: Some("video texture"),
-            size: wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let texture_view = VideoResources::create_texture(gpu, width, height);
         let sampler = gpu.create_sampler(&wgpu::SamplerDescriptor {
             mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Linear,
@@ -110,24 +175,13 @@ impl VideoTarget {
             mapped_at_creation: false,
         });
 
-        let bind_group = gpu.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("video bind group"),
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: uniform_buffer.as_entire_binding(),
-                },
-            ],
-        });
+        let bind_group = VideoResources::create_bind_group(
+            gpu,
+            &bind_group_layout,
+            &texture_view,
+            &sampler,
+            &uniform_buffer,
+        );
 
         let shader = gpu.create_shader_module(wgpu::include_wgsl!("video.wgsl"));
         let pipeline_layout = gpu.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -135,38 +189,43 @@ impl VideoTarget {
             bind_group_layouts: &[&bind_group_layout],
             push_constant_ranges: &[],
         });
-        let pipeline = gpu.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("video pipeline"),
-            cache: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[],
-                compilation_options: Default::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: Default::default(),
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: sample_count,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+        let pipelines = ScalingMode::ALL
+            .iter()
+            .map(|mode| {
+                gpu.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("video pipeline"),
+                    cache: None,
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: Default::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: Some(mode.entry_point()),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: config.format,
+                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: Default::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState {
+                        count: sample_count,
+                        mask: !0,
+                        alpha_to_coverage_enabled: false,
+                    },
+                    multiview: None,
+                })
+            })
+            .collect();
 
         let resources = VideoResources {
-            pipeline,
+            pipelines,
             sampler,
             texture_view,
             uniform_buffer,
@@ -177,13 +236,81 @@ impl VideoTarget {
         Self {
             resources,
             receiver,
+            serial: 0,
             updated: false,
             first_pts: None,
             first_instant: None,
             clock,
             next_frame_slot: None,
             last_pts_seconds: None,
+            frame_interval: None,
+            shown_pts: Default::default(),
+            shader_chain: None,
+            shader_bind_group: None,
+        }
+    }
+
+    /// Rebuilds the shader chain if the requested pass list changed.
+    fn sync_shaders(&mut self, gpu: &wgpu::Device, shaders: &Arc<[PathBuf]>) {
+        let unchanged = match &self.shader_chain {
+            Some(chain) => chain.paths() == shaders,
+            None => shaders.is_empty(),
+        };
+        if unchanged {
+            return;
+        }
+
+        self.shader_chain = (!shaders.is_empty())
+            .then(|| ShaderChain::new(gpu, shaders.clone(), &self.resources.texture_view));
+        self.refresh_shader_bind_group(gpu);
+        // run the new chain even if playback is paused
+        self.updated = true;
+    }
+
+    /// Recreates the video texture and everything sampling it for frames of a new size.
+    fn resize(&mut self, gpu: &wgpu::Device, width: u32, height: u32) {
+        let resources = &mut self.resources;
+        resources.texture_view = VideoResources::create_texture(gpu, width, height);
+        resources.bind_group = VideoResources::create_bind_group(
+            gpu,
+            &resources.bind_group_layout,
+            &resources.texture_view,
+            &resources.sampler,
+            &resources.uniform_buffer,
+        );
+        if let Some(chain) = &self.shader_chain {
+            let paths = chain.paths().clone();
+            self.shader_chain = Some(ShaderChain::new(gpu, paths, &resources.texture_view));
         }
+        self.refresh_shader_bind_group(gpu);
+    }
+
+    /// Points the display pass at the shader chain output, or back at the raw video texture.
+    fn refresh_shader_bind_group(&mut self, gpu: &wgpu::Device) {
+        let output = self.shader_chain.as_ref().and_then(|chain| chain.output());
+        self.shader_bind_group = output.map(|view| {
+            VideoResources::create_bind_group(
+                gpu,
+                &self.resources.bind_group_layout,
+                view,
+                &self.resources.sampler,
+                &self.resources.uniform_buffer,
+            )
+        });
+    }
+
+    /// Forgets the frame timing of the previous position after a seek with `serial`.
+    fn start_serial(&mut self, serial: u64) {
+        self.serial = serial;
+        self.next_frame_slot = None;
+        self.last_pts_seconds = None;
+        self.frame_interval = None;
+    }
+
+    fn display_bind_group(&self) -> &wgpu::BindGroup {
+        self.shader_bind_group
+            .as_ref()
+            .unwrap_or(&self.resources.bind_group)
     }
 }
 
@@ -204,16 +331,36 @@ impl VideoPipeline {
 #[derive(Clone)]
 pub struct VideoCommand {
     pub id: Uuid,
+    /// Stream size the texture starts at; it follows the size of the frames received
     pub width: u32,
     pub height: u32,
-    pub receiver: flume::Receiver<(Vec<u8>, f64)>,
+    pub receiver: flume::Receiver<VideoFrame>,
+    /// Bumped on every seek, so frames of the old position are not shown
+    pub serial: u64,
+    /// Receives the pts of the frame on screen
+    pub shown_pts: Arc<RwLock<Option<f64>>>,
     pub clock: GlobalClock,
+    /// User WGSL passes applied in order before display
+    pub shaders: Arc<[PathBuf]>,
+    /// Brightness, contrast and other live adjustments applied at display time
+    pub picture: PictureAdjustments,
+    /// Kernel used to scale the video to its on-screen size
+    pub scaling: ScalingMode,
+    /// Sharpening strength for [`ScalingMode::Cas`], in [0, 1]
+    pub sharpness: f32,
 }
 
 impl PartialEq for VideoCommand {
     fn eq(&self, other: &Self) -> bool {
         // compare id to ensure same playback target; require clock to be paused to avoid race conditions
-        self.id == other.id && self.clock.is_paused()
+        // and compare display settings so adjustments made while paused are still redrawn
+        self.id == other.id
+            && self.clock.is_paused()
+            && self.serial == other.serial
+            && self.picture == other.picture
+            && self.scaling == other.scaling
+            && self.sharpness == other.sharpness
+            && self.shaders == other.shaders
     }
 }
 
@@ -222,7 +369,7 @@ impl DrawCommand for VideoCommand {}
 impl DrawablePipeline<VideoCommand> for VideoPipeline {
     fn begin_frame(
         &mut self,
-        _gpu: &tessera_ui::wgpu::Device,
+        gpu: &tessera_ui::wgpu::Device,
         gpu_queue: &tessera_ui::wgpu::Queue,
         _config: &tessera_ui::wgpu::SurfaceConfiguration,
     ) {
@@ -231,21 +378,59 @@ impl DrawablePipeline<VideoCommand> for VideoPipeline {
         const DROP_THRESHOLD: f64 = 0.15; // seconds
 
         for target in self.video_targets.values_mut() {
-            let now = target.clock.now();
-
-            // fill single-frame slot to hold the next frame for scheduling decisions
-            if target.next_frame_slot.is_none()
-                && let Ok((frame_data, pts)) = target.receiver.try_recv()
+            // pick up edits to user shaders so they can be tuned while watching
+            if let Some(chain) = target.shader_chain.as_mut()
+                && chain.reload_changed(gpu)
             {
-                target.next_frame_slot = Some((frame_data, pts));
+                target.refresh_shader_bind_group(gpu);
+                target.updated = true;
             }
 
-            // Evaluate slot by temporarily taking it to avoid simultaneous borrows
-            if let Some(slot) = target.next_frame_slot.take() {
-                let (frame_data, pts_seconds) = slot;
+            let now = target.clock.now();
+            // double-rate (deinterlaced) content has frames closer together than the default
+            // tolerance, so never show a frame more than half an interval early
+            let tolerance = target.frame_interval.map_or(TOLERANCE_SHOW, |interval| {
+                TOLERANCE_SHOW.min(interval / 2.0)
+            });
+
+            loop {
+                // fill single-frame slot to hold the next frame for scheduling decisions
+                if target.next_frame_slot.is_none()
+                    && let Ok(frame) = target.receiver.try_recv()
+                {
+                    if frame.serial < target.serial {
+                        // decoded before the latest seek
+                        continue;
+                    }
+                    if frame.serial > target.serial {
+                        target.start_serial(frame.serial);
+                    }
+                    target.next_frame_slot = Some(frame);
+                }
+
+                // Evaluate slot by temporarily taking it to avoid simultaneous borrows
+                let Some(frame) = target.next_frame_slot.take() else {
+                    break;
+                };
+                let pts_seconds = frame.pts;
                 // decide whether to show, drop, or wait for the correct display time
-                if pts_seconds <= now + TOLERANCE_SHOW {
-                    // show frame
+                let stale = pts_seconds < now - DROP_THRESHOLD;
+                if stale && target.clock.mode() == ClockMode::Video {
+                    // video is the master: hold the clock back to the late frame instead of
+                    // dropping it, and let audio follow
+                    target.clock.sync(ClockMode::Video, pts_seconds);
+                } else if stale {
+                    // drop stale frame to avoid excessive latency; keep draining so high frame
+                    // rates can catch up within a single display refresh
+                    continue;
+                }
+
+                if stale || pts_seconds <= now + tolerance {
+                    // show frame; filters such as crop or scale change the frame size
+                    let size = target.resources.texture_view.texture().size();
+                    if (size.width, size.height) != (frame.width, frame.height) {
+                        target.resize(gpu, frame.width, frame.height);
+                    }
                     gpu_queue.write_texture(
                         wgpu::TexelCopyTextureInfo {
                             texture: target.resources.texture_view.texture(),
@@ -253,32 +438,35 @@ impl DrawablePipeline<VideoCommand> for VideoPipeline {
                             origin: wgpu::Origin3d::ZERO,
                             aspect: wgpu::TextureAspect::All,
                         },
-                        &frame_data,
+                        &frame.rgba,
                         wgpu::TexelCopyBufferLayout {
                             offset: 0,
-                            bytes_per_row: Some(
-                                4 * target.resources.texture_view.texture().size().width,
-                            ),
+                            bytes_per_row: Some(4 * frame.width),
                             rows_per_image: None,
                         },
                         wgpu::Extent3d {
-                            width: target.resources.texture_view.texture().size().width,
-                            height: target.resources.texture_view.texture().size().height,
+                            width: frame.width,
+                            height: frame.height,
                             depth_or_array_layers: 1,
                         },
                     );
                     target.updated = true;
+                    if let Some(last) = target.last_pts_seconds
+                        && pts_seconds > last
+                    {
+                        target.frame_interval = Some(pts_seconds - last);
+                    }
                     target.last_pts_seconds = Some(pts_seconds);
+                    *target.shown_pts.write() = Some(pts_seconds);
                     if target.first_pts.is_none() {
                         target.first_pts = Some(pts_seconds);
                         target.first_instant = Some(Instant::now());
                     }
-                } else if pts_seconds < now - DROP_THRESHOLD {
-                    // drop stale frame to avoid excessive latency
                 } else {
                     // future frame: put it back and wait until its presentation time
-                    target.next_frame_slot = Some((frame_data, pts_seconds));
+                    target.next_frame_slot = Some(frame);
                 }
+                break;
             }
         }
     }
@@ -297,23 +485,39 @@ impl DrawablePipeline<VideoCommand> for VideoPipeline {
             if !self.video_targets.contains_key(&cmd.id) {
                 // create a new VideoTarget to manage per-target resources and timing
                 let rx = cmd.receiver.clone();
-                self.video_targets.insert(
-                    cmd.id,
-                    VideoTarget::new(
-                        gpu,
-                        config,
-                        rx,
-                        self.sample_count,
-                        cmd.width,
-                        cmd.height,
-                        cmd.clock.clone(),
-                    ),
+                let mut target = VideoTarget::new(
+                    gpu,
+                    config,
+                    rx,
+                    self.sample_count,
+                    cmd.width,
+                    cmd.height,
+                    cmd.clock.clone(),
                 );
+                target.shown_pts = cmd.shown_pts.clone();
+                self.video_targets.insert(cmd.id, target);
             }
 
-            // sample video texture into the render target for drawing
             let target = self.video_targets.get_mut(&cmd.id).unwrap();
-            render_pass.set_pipeline(&target.resources.pipeline);
+            if cmd.serial > target.serial {
+                target.start_serial(cmd.serial);
+            }
+            target.sync_shaders(gpu, &cmd.shaders);
+            if target.updated {
+                // post-process the new frame before it is sampled below
+                if let Some(chain) = target.shader_chain.as_mut() {
+                    chain.run(gpu, gpu_queue, target.last_pts_seconds.unwrap_or(0.0));
+                }
+                target.updated = false;
+            }
+
+            // sample video texture into the render target for drawing
+            let source = target.resources.texture_view.texture().size();
+            let mode_index = ScalingMode::ALL
+                .iter()
+                .position(|&mode| mode == cmd.scaling)
+                .unwrap();
+            render_pass.set_pipeline(&target.resources.pipelines[mode_index]);
             let uniforms = VideoUniforms {
                 rect: Vec4::new(
                     pos.x.0 as f32 / config.width as f32,
@@ -321,11 +525,16 @@ impl DrawablePipeline<VideoCommand> for VideoPipeline {
                     size.width.0 as f32 / config.width as f32,
                     size.height.0 as f32 / config.height as f32,
                 ),
+                picture: cmd.picture.factors(),
+                source_size: Vec2::new(source.width as f32, source.height as f32),
+                output_size: Vec2::new(size.width.0 as f32, size.height.0 as f32),
+                hue: cmd.picture.hue_radians(),
+                sharpness: cmd.sharpness,
             };
             let mut buffer = UniformBuffer::new(Vec::new());
             buffer.write(&uniforms).unwrap();
             gpu_queue.write_buffer(&target.resources.uniform_buffer, 0, &buffer.into_inner());
-            render_pass.set_bind_group(0, &target.resources.bind_group, &[]);
+            render_pass.set_bind_group(0, target.display_bind_group(), &[]);
             render_pass.draw(0..6, 0..1); // two triangles forming a rectangle
         }
     }
@@ -0,0 +1,52 @@
+/// Kernel used by the display pass to scale the video to its on-screen size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ScalingMode {
+    /// Hardware bilinear filtering
+    #[default]
+    Bilinear,
+    /// Sharp bicubic (B=0, C=0.5)
+    CatmullRom,
+    /// Soft bicubic with little ringing (B=C=1/3)
+    Mitchell,
+    /// Three-lobe Lanczos, the sharpest and most expensive
+    Lanczos,
+    /// Bilinear upscale followed by contrast adaptive sharpening
+    Cas,
+}
+
+impl ScalingMode {
+    pub const ALL: [ScalingMode; 5] = [
+        ScalingMode::Bilinear,
+        ScalingMode::CatmullRom,
+        ScalingMode::Mitchell,
+        ScalingMode::Lanczos,
+        ScalingMode::Cas,
+    ];
+
+    /// Fragment entry point in `video.wgsl` implementing this kernel.
+    pub fn entry_point(self) -> &'static str {
+        match self {
+            ScalingMode::Bilinear => "fs_bilinear",
+            ScalingMode::CatmullRom => "fs_catmull_rom",
+            ScalingMode::Mitchell => "fs_mitchell",
+            ScalingMode::Lanczos => "fs_lanczos",
+            ScalingMode::Cas => "fs_cas",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ScalingMode::Bilinear => "Bilinear",
+            ScalingMode::CatmullRom => "Catmull-Rom",
+            ScalingMode::Mitchell => "Mitchell",
+            ScalingMode::Lanczos => "Lanczos",
+            ScalingMode::Cas => "CAS",
+        }
+    }
+
+    /// The next mode in [`ScalingMode::ALL`], wrapping around.
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&mode| mode == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
@@ -0,0 +1,571 @@
+use std::sync::Arc;
+
+use encase::{ShaderType, UniformBuffer};
+use glam::{Vec2, Vec4};
+use parking_lot::RwLock;
+use tessera_ui::{
+    Color, ComputedData, Constraint, CursorEventContent, DimensionValue, Dp, DrawCommand,
+    DrawablePipeline, PressKeyEventType, tessera, wgpu,
+};
+use tessera_ui_basic_components::{
+    alignment::Alignment,
+    boxed::{BoxedArgs, boxed},
+    surface::{SurfaceArgs, surface},
+    text::{TextArgs, text},
+};
+
+use crate::media::{VideoPlayerState, thumbnail::Thumbnail};
+
+// size of the chapter array in seek_bar.wgsl
+const MAX_CHAPTERS: usize = 64;
+// height of the clickable track, the time labels sit below it
+const TRACK_HEIGHT: f64 = 24.0; // dp
+const BAR_HEIGHT: f64 = 72.0; // dp
+// space between the thumbnail and the track
+const THUMBNAIL_MARGIN: f32 = 12.0; // pixels
+
+#[derive(ShaderType)]
+struct SeekBarUniforms {
+    rect: Vec4,         // x, y, w, h in screen-normalized coordinates
+    size: Vec2,         // quad size in pixels
+    progress: f32,      // played fraction of the bar
+    hover: f32,         // fraction under the cursor, negative when not hovering
+    chapter_count: u32, // number of chapter starts in use
+    opacity: f32,       // fades the whole bar along with the controls
+    chapters: [Vec4; MAX_CHAPTERS / 4],
+}
+
+/// Formats seconds as `m:ss`, or `h:mm:ss` from an hour on.
+pub fn format_time(seconds: f64) -> String {
+    let total = seconds.max(0.0) as u64;
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+/// Pointer state of the seek bar, kept across frames.
+#[derive(Default)]
+pub struct SeekBarState {
+    // fraction of the bar under the cursor, or where it was let go while dragging
+    hover: RwLock<Option<f32>>,
+    dragging: RwLock<bool>,
+}
+
+impl SeekBarState {
+    /// Whether the pointer is over the bar or dragging it, so it should stay visible.
+    pub fn is_active(&self) -> bool {
+        self.hover.read().is_some() || *self.dragging.read()
+    }
+}
+
+/// Time display and seek track; dragging seeks on release and hovering previews the time.
+#[tessera]
+pub fn seek_bar(state: Arc<SeekBarState>, player: Arc<RwLock<VideoPlayerState>>, opacity: f32) {
+    let (elapsed, total, preview) = {
+        let player = player.read();
+        let duration = player.duration();
+        let hover = *state.hover.read();
+        let hover_time = hover
+            .zip(duration)
+            .map(|(hover, duration)| hover as f64 * duration);
+        // while dragging, the time shown is where playback will continue
+        let position = match hover_time {
+            Some(time) if *state.dragging.read() => time,
+            _ => player.position(),
+        };
+        let preview = hover_time.map(|time| match player.chapter_at(time) {
+            Some(chapter) => match &chapter.title {
+                Some(title) => format!("{} · {}", format_time(time), title),
+                None => format_time(time),
+            },
+            None => format_time(time),
+        });
+        (
+            format_time(position),
+            duration.map_or_else(|| "--:--".to_string(), format_time),
+            preview.unwrap_or_default(),
+        )
+    };
+
+    surface(
+        SurfaceArgs {
+            width: DimensionValue::FILLED,
+            height: Dp(BAR_HEIGHT).into(),
+            style: Color::TRANSPARENT.into(),
+            padding: Dp(12.0),
+            ..Default::default()
+        },
+        None,
+        move || {
+            boxed(
+                BoxedArgs {
+                    alignment: Alignment::TopCenter,
+                    width: DimensionValue::FILLED,
+                    height: DimensionValue::FILLED,
+                },
+                move |scope| {
+                    scope.child(move || seek_track(state, player, opacity));
+                    scope.child(move || time_label(Alignment::BottomStart, elapsed, opacity));
+                    scope.child(move || time_label(Alignment::BottomCenter, preview, opacity));
+                    scope.child(move || time_label(Alignment::BottomEnd, total, opacity));
+                },
+            );
+        },
+    );
+}
+
+#[tessera]
+fn time_label(alignment: Alignment, content: String, opacity: f32) {
+    boxed(
+        BoxedArgs {
+            alignment,
+            width: DimensionValue::FILLED,
+            height: DimensionValue::FILLED,
+        },
+        move |scope| {
+            scope.child(move || {
+                text(TextArgs {
+                    text: content,
+                    size: Dp(16.0),
+                    color: Color::WHITE.with_alpha(opacity),
+                    ..Default::default()
+                });
+            });
+        },
+    );
+}
+
+#[tessera]
+fn seek_track(state: Arc<SeekBarState>, player: Arc<RwLock<VideoPlayerState>>, opacity: f32) {
+    {
+        let state = state.clone();
+        let player = player.clone();
+        state_handler(Box::new(move |input| {
+            let width = input.computed_data.width.0.max(1) as f32;
+            let height = input.computed_data.height.0;
+            let cursor = input.cursor_position_rel;
+            let inside = cursor.is_some_and(|position| {
+                position.x.0 >= 0
+                    && (position.x.0 as f32) < width
+                    && position.y.0 >= 0
+                    && position.y.0 < height
+            });
+            let fraction = cursor.map(|position| (position.x.0 as f32 / width).clamp(0.0, 1.0));
+
+            let mut dragging = *state.dragging.read();
+            for event in input.cursor_events.iter() {
+                match event.content {
+                    CursorEventContent::Pressed(PressKeyEventType::Left) if inside => {
+                        dragging = true;
+                    }
+                    CursorEventContent::Released(PressKeyEventType::Left) if dragging => {
+                        dragging = false;
+                        let mut player = player.write();
+                        if let Some((fraction, duration)) =
+                            fraction.or(*state.hover.read()).zip(player.duration())
+                        {
+                            player.seek(fraction as f64 * duration);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            *state.dragging.write() = dragging;
+            if inside || dragging {
+                // keep the last position if the cursor leaves the window mid-drag
+                if fraction.is_some() {
+                    *state.hover.write() = fraction;
+                }
+                // the scrim below would take the click as play/pause
+                input.cursor_events.clear();
+            } else {
+                *state.hover.write() = None;
+            }
+        }));
+    }
+
+    measure(Box::new(move |input| {
+        let player = player.read();
+        let hover = *state.hover.read();
+        let command = match player.duration() {
+            Some(duration) if duration > 0.0 => {
+                let progress = match hover {
+                    Some(hover) if *state.dragging.read() => hover,
+                    _ => (player.position() / duration) as f32,
+                };
+                SeekBarCommand {
+                    progress,
+                    hover,
+                    opacity,
+                    chapters: player
+                        .chapters()
+                        .iter()
+                        .map(|chapter| (chapter.start / duration) as f32)
+                        .collect(),
+                    thumbnail: hover.and_then(|hover| player.thumbnail(hover as f64 * duration)),
+                }
+            }
+            // nothing to seek in, e.g. a live stream
+            _ => SeekBarCommand {
+                progress: 0.0,
+                hover: None,
+                opacity,
+                chapters: Vec::new(),
+                thumbnail: None,
+            },
+        };
+        input.metadata_mut().push_draw_command(command);
+
+        let size = Constraint::new(DimensionValue::FILLED, Dp(TRACK_HEIGHT).into())
+            .merge(input.parent_constraint);
+        Ok(ComputedData {
+            width: size.width.get_max().unwrap(),
+            height: size.height.get_max().unwrap(),
+        })
+    }))
+}
+
+/// Draws the seek track, chapter ticks and the hover thumbnail.
+#[derive(Clone)]
+pub struct SeekBarCommand {
+    pub progress: f32,
+    pub hover: Option<f32>,
+    pub opacity: f32,
+    /// Chapter starts as fractions of the bar
+    pub chapters: Vec<f32>,
+    pub thumbnail: Option<Arc<Thumbnail>>,
+}
+
+impl PartialEq for SeekBarCommand {
+    fn eq(&self, other: &Self) -> bool {
+        let same_thumbnail = match (&self.thumbnail, &other.thumbnail) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.progress == other.progress
+            && self.hover == other.hover
+            && self.opacity == other.opacity
+            && self.chapters == other.chapters
+            && same_thumbnail
+    }
+}
+
+impl DrawCommand for SeekBarCommand {}
+
+struct SeekBarResources {
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    bar_pipeline: wgpu::RenderPipeline,
+    thumbnail_pipeline: wgpu::RenderPipeline,
+    // the bar and the thumbnail are separate quads, each with its own uniforms
+    bar_uniforms: wgpu::Buffer,
+    thumbnail_uniforms: wgpu::Buffer,
+    bar_bind_group: wgpu::BindGroup,
+    thumbnail_bind_group: wgpu::BindGroup,
+    texture: wgpu::Texture,
+    // the thumbnail currently in `texture`
+    shown: Option<Arc<Thumbnail>>,
+}
+
+impl SeekBarResources {
+    fn new(gpu: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32) -> Self {
+        let sampler = gpu.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = gpu.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("seek bar bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let uniform_buffer = |label| {
+            gpu.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: SeekBarUniforms::min_size().get(),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        let bar_uniforms = uniform_buffer("seek bar uniform buffer");
+        let thumbnail_uniforms = uniform_buffer("seek bar thumbnail uniform buffer");
+
+        let shader = gpu.create_shader_module(wgpu::include_wgsl!("seek_bar.wgsl"));
+        let pipeline_layout = gpu.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("seek bar pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |label, entry_point| {
+            gpu.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                cache: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some(entry_point),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        };
+        let bar_pipeline = pipeline("seek bar pipeline", "fs_bar");
+        let thumbnail_pipeline = pipeline("seek bar thumbnail pipeline", "fs_thumbnail");
+
+        // replaced by the first thumbnail; the bar itself never samples it
+        let texture = create_texture(gpu, 1, 1);
+        let (bar_bind_group, thumbnail_bind_group) = create_bind_groups(
+            gpu,
+            &bind_group_layout,
+            &texture,
+            &sampler,
+            &bar_uniforms,
+            &thumbnail_uniforms,
+        );
+
+        Self {
+            bind_group_layout,
+            sampler,
+            bar_pipeline,
+            thumbnail_pipeline,
+            bar_uniforms,
+            thumbnail_uniforms,
+            bar_bind_group,
+            thumbnail_bind_group,
+            texture,
+            shown: None,
+        }
+    }
+
+    /// Uploads `thumbnail` unless it is already shown, resizing the texture if needed.
+    fn show(&mut self, gpu: &wgpu::Device, gpu_queue: &wgpu::Queue, thumbnail: &Arc<Thumbnail>) {
+        if self
+            .shown
+            .as_ref()
+            .is_some_and(|shown| Arc::ptr_eq(shown, thumbnail))
+        {
+            return;
+        }
+        let size = self.texture.size();
+        if size.width != thumbnail.width || size.height != thumbnail.height {
+            self.texture = create_texture(gpu, thumbnail.width, thumbnail.height);
+            (self.bar_bind_group, self.thumbnail_bind_group) = create_bind_groups(
+                gpu,
+                &self.bind_group_layout,
+                &self.texture,
+                &self.sampler,
+                &self.bar_uniforms,
+                &self.thumbnail_uniforms,
+            );
+        }
+        gpu_queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &thumbnail.rgba,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * thumbnail.width),
+                rows_per_image: None,
+            },
+            self.texture.size(),
+        );
+        self.shown = Some(thumbnail.clone());
+    }
+}
+
+fn create_texture(gpu: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+    gpu.create_texture(&wgpu::TextureDescriptor {
+        label: Some("seek bar thumbnail texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+
+/// Bind groups of the bar and thumbnail quads, sharing the thumbnail texture.
+fn create_bind_groups(
+    gpu: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    texture: &wgpu::Texture,
+    sampler: &wgpu::Sampler,
+    bar_uniforms: &wgpu::Buffer,
+    thumbnail_uniforms: &wgpu::Buffer,
+) -> (wgpu::BindGroup, wgpu::BindGroup) {
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let bind_group = |uniforms: &wgpu::Buffer| {
+        gpu.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("seek bar bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniforms.as_entire_binding(),
+                },
+            ],
+        })
+    };
+    (bind_group(bar_uniforms), bind_group(thumbnail_uniforms))
+}
+
+fn write_uniforms(gpu_queue: &wgpu::Queue, buffer: &wgpu::Buffer, uniforms: &SeekBarUniforms) {
+    let mut data = UniformBuffer::new(Vec::new());
+    data.write(uniforms).unwrap();
+    gpu_queue.write_buffer(buffer, 0, &data.into_inner());
+}
+
+pub struct SeekBarPipeline {
+    resources: Option<SeekBarResources>,
+    sample_count: u32,
+}
+
+impl SeekBarPipeline {
+    pub fn new(sample_count: u32) -> Self {
+        Self {
+            resources: None,
+            sample_count,
+        }
+    }
+}
+
+impl DrawablePipeline<SeekBarCommand> for SeekBarPipeline {
+    fn draw(
+        &mut self,
+        gpu: &tessera_ui::wgpu::Device,
+        gpu_queue: &tessera_ui::wgpu::Queue,
+        config: &tessera_ui::wgpu::SurfaceConfiguration,
+        render_pass: &mut tessera_ui::wgpu::RenderPass<'_>,
+        commands: &[(&SeekBarCommand, tessera_ui::PxSize, tessera_ui::PxPosition)],
+        _scene_texture_view: &tessera_ui::wgpu::TextureView,
+        _clip_rect: Option<tessera_ui::PxRect>,
+    ) {
+        let resources = self
+            .resources
+            .get_or_insert_with(|| SeekBarResources::new(gpu, config, self.sample_count));
+        let screen = Vec2::new(config.width as f32, config.height as f32);
+
+        for (cmd, size, pos) in commands {
+            let origin = Vec2::new(pos.x.0 as f32, pos.y.0 as f32);
+            let extent = Vec2::new(size.width.0 as f32, size.height.0 as f32);
+            let mut chapters = [Vec4::ZERO; MAX_CHAPTERS / 4];
+            for (index, &start) in cmd.chapters.iter().take(MAX_CHAPTERS).enumerate() {
+                chapters[index / 4][index % 4] = start;
+            }
+            let uniforms = SeekBarUniforms {
+                rect: Vec4::new(
+                    origin.x / screen.x,
+                    origin.y / screen.y,
+                    extent.x / screen.x,
+                    extent.y / screen.y,
+                ),
+                size: extent,
+                progress: cmd.progress.clamp(0.0, 1.0),
+                hover: cmd.hover.unwrap_or(-1.0),
+                chapter_count: cmd.chapters.len().min(MAX_CHAPTERS) as u32,
+                opacity: cmd.opacity,
+                chapters,
+            };
+            write_uniforms(gpu_queue, &resources.bar_uniforms, &uniforms);
+            render_pass.set_pipeline(&resources.bar_pipeline);
+            render_pass.set_bind_group(0, &resources.bar_bind_group, &[]);
+            render_pass.draw(0..6, 0..1);
+
+            let (Some(hover), Some(thumbnail)) = (cmd.hover, &cmd.thumbnail) else {
+                continue;
+            };
+            resources.show(gpu, gpu_queue, thumbnail);
+            // centered over the cursor above the track, kept inside the window
+            let thumbnail_size = Vec2::new(thumbnail.width as f32, thumbnail.height as f32);
+            let x = (origin.x + hover * extent.x - thumbnail_size.x / 2.0)
+                .clamp(0.0, (screen.x - thumbnail_size.x).max(0.0));
+            let y = (origin.y - thumbnail_size.y - THUMBNAIL_MARGIN).max(0.0);
+            let uniforms = SeekBarUniforms {
+                rect: Vec4::new(
+                    x / screen.x,
+                    y / screen.y,
+                    thumbnail_size.x / screen.x,
+                    thumbnail_size.y / screen.y,
+                ),
+                size: thumbnail_size,
+                progress: 0.0,
+                hover: -1.0,
+                chapter_count: 0,
+                opacity: cmd.opacity,
+                chapters: [Vec4::ZERO; MAX_CHAPTERS / 4],
+            };
+            write_uniforms(gpu_queue, &resources.thumbnail_uniforms, &uniforms);
+            render_pass.set_pipeline(&resources.thumbnail_pipeline);
+            render_pass.set_bind_group(0, &resources.thumbnail_bind_group, &[]);
+            render_pass.draw(0..6, 0..1);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+struct SeekBarUniforms {
+    rect: vec4<f32>,     // x, y, w, h in screen-normalized coordinates
+    size: vec2<f32>,     // quad size in pixels
+    progress: f32,       // played fraction of the bar
+    hover: f32,          // fraction under the cursor, negative when not hovering
+    chapter_count: u32,  // number of chapter starts in use
+    opacity: f32,        // fades the whole bar along with the controls
+    // chapter starts as fractions of the bar, four per vec4
+    chapters: array<vec4<f32>, 16>,
+};
+
+@group(0) @binding(0) var thumbnail_tex: texture_2d<f32>;
+@group(0) @binding(1) var thumbnail_sampler: sampler;
+@group(0) @binding(2) var<uniform> uniforms: SeekBarUniforms;
+
+const TRACK_THICKNESS: f32 = 4.0;  // pixels
+const HOVER_THICKNESS: f32 = 6.0;  // pixels
+const KNOB_RADIUS: f32 = 7.0;      // pixels
+const TICK_GAP: f32 = 3.0;         // pixels
+const BORDER: f32 = 2.0;           // pixels around the thumbnail
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) idx: u32) -> VertexOutput {
+    var quad = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0)
+    );
+    let uv = quad[idx];
+    let screen_pos = uniforms.rect.xy + uv * uniforms.rect.zw;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(screen_pos.x * 2.0 - 1.0, 1.0 - screen_pos.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn chapter_start(index: u32) -> f32 {
+    let packed = uniforms.chapters[index / 4u];
+    switch index % 4u {
+        case 0u: { return packed.x; }
+        case 1u: { return packed.y; }
+        case 2u: { return packed.z; }
+        default: { return packed.w; }
+    }
+}
+
+@fragment
+fn fs_bar(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = in.uv * uniforms.size;
+    let center_y = uniforms.size.y * 0.5;
+    let hovering = uniforms.hover >= 0.0;
+    let thickness = select(TRACK_THICKNESS, HOVER_THICKNESS, hovering);
+
+    var color = vec4<f32>(0.0);
+    if abs(pixel.y - center_y) <= thickness * 0.5 {
+        var alpha = 0.3;
+        if in.uv.x <= uniforms.progress {
+            alpha = 0.95;
+        } else if hovering && in.uv.x <= uniforms.hover {
+            alpha = 0.55;
+        }
+        color = vec4<f32>(1.0, 1.0, 1.0, alpha);
+
+        // chapters split the track into segments
+        for (var i = 0u; i < uniforms.chapter_count; i++) {
+            let x = chapter_start(i) * uniforms.size.x;
+            if x > 0.0 && abs(pixel.x - x) < TICK_GAP * 0.5 {
+                color = vec4<f32>(0.0);
+            }
+        }
+    }
+
+    let knob = vec2<f32>(uniforms.progress * uniforms.size.x, center_y);
+    let distance = length(pixel - knob);
+    if hovering && distance <= KNOB_RADIUS {
+        // soften the edge by one pixel
+        let edge = clamp(KNOB_RADIUS - distance, 0.0, 1.0);
+        color = mix(color, vec4<f32>(1.0), edge);
+    }
+    return vec4<f32>(color.rgb, color.a * uniforms.opacity);
+}
+
+@fragment
+fn fs_thumbnail(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = in.uv * uniforms.size;
+    let inner = uniforms.size - vec2<f32>(BORDER);
+    if any(pixel < vec2<f32>(BORDER)) || any(pixel > inner) {
+        return vec4<f32>(1.0, 1.0, 1.0, 0.9 * uniforms.opacity);
+    }
+    let uv = (pixel - vec2<f32>(BORDER)) / (uniforms.size - vec2<f32>(2.0 * BORDER));
+    let picture = textureSampleLevel(thumbnail_tex, thumbnail_sampler, uv, 0.0);
+    return vec4<f32>(picture.rgb, uniforms.opacity);
+}
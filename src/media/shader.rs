@@ -0,0 +1,370 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use encase::{ShaderType, UniformBuffer};
+use glam::Vec2;
+use tessera_ui::wgpu::{self, naga};
+use tracing::{error, info};
+
+/// Bindings and the vertex stage shared by every user pass.
+const PRELUDE: &str = include_str!("shader_prelude.wgsl");
+// keep extra precision between passes so chained effects do not band
+const INTERMEDIATE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// how often shader files are checked for changes on disk
+const RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(ShaderType)]
+struct PassUniforms {
+    source_size: Vec2,
+    frame_time: f32,
+    frame_count: u32,
+}
+
+struct ShaderPass {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    // None while the file is missing or fails to compile; the pass is then skipped
+    pipeline: Option<wgpu::RenderPipeline>,
+    uniform_buffer: wgpu::Buffer,
+    output: wgpu::TextureView,
+    // samples the previous enabled pass, or the video texture for the first one
+    bind_group: Option<wgpu::BindGroup>,
+}
+
+/// User-supplied WGSL fragment passes applied to the video texture before it is displayed.
+///
+/// Each file is appended to `shader_prelude.wgsl` and must define `fs_main`. Every pass renders
+/// at the video's resolution into an intermediate target, so a pass's output is the size of its
+/// source; scaling to the window happens afterwards. Passes are recompiled when their file changes.
+pub struct ShaderChain {
+    paths: Arc<[PathBuf]>,
+    passes: Vec<ShaderPass>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    sampler: wgpu::Sampler,
+    source: wgpu::TextureView,
+    frame_count: u32,
+    last_reload_check: Instant,
+}
+
+impl ShaderChain {
+    pub fn new(gpu: &wgpu::Device, paths: Arc<[PathBuf]>, source: &wgpu::TextureView) -> Self {
+        let size = source.texture().size();
+
+        let bind_group_layout = gpu.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("shader pass bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = gpu.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("shader pass pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let sampler = gpu.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let passes = paths
+            .iter()
+            .map(|path| {
+                let output = gpu.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("shader pass target"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: INTERMEDIATE_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                });
+                let uniform_buffer = gpu.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("shader pass uniform buffer"),
+                    size: PassUniforms::min_size().get(),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                ShaderPass {
+                    path: path.clone(),
+                    modified: modified_time(path),
+                    pipeline: compile_pass(gpu, &pipeline_layout, path),
+                    uniform_buffer,
+                    output: output.create_view(&wgpu::TextureViewDescriptor::default()),
+                    bind_group: None,
+                }
+            })
+            .collect();
+
+        let mut chain = Self {
+            paths,
+            passes,
+            bind_group_layout,
+            pipeline_layout,
+            sampler,
+            source: source.clone(),
+            frame_count: 0,
+            last_reload_check: Instant::now(),
+        };
+        chain.rebind(gpu);
+        chain
+    }
+
+    pub fn paths(&self) -> &Arc<[PathBuf]> {
+        &self.paths
+    }
+
+    /// Output of the last enabled pass, or None if no pass compiled.
+    pub fn output(&self) -> Option<&wgpu::TextureView> {
+        self.passes
+            .iter()
+            .rev()
+            .find(|pass| pass.pipeline.is_some())
+            .map(|pass| &pass.output)
+    }
+
+    /// Recompiles passes whose file changed on disk; returns true if any pass was reloaded.
+    pub fn reload_changed(&mut self, gpu: &wgpu::Device) -> bool {
+        if self.last_reload_check.elapsed() < RELOAD_INTERVAL {
+            return false;
+        }
+        self.last_reload_check = Instant::now();
+
+        let mut changed = false;
+        for pass in &mut self.passes {
+            let modified = modified_time(&pass.path);
+            if modified != pass.modified {
+                pass.modified = modified;
+                pass.pipeline = compile_pass(gpu, &self.pipeline_layout, &pass.path);
+                changed = true;
+            }
+        }
+        if changed {
+            self.rebind(gpu);
+        }
+        changed
+    }
+
+    /// Renders every enabled pass for the current video frame.
+    pub fn run(&mut self, gpu: &wgpu::Device, gpu_queue: &wgpu::Queue, frame_time: f64) {
+        let size = self.source.texture().size();
+        let size = Vec2::new(size.width as f32, size.height as f32);
+        let mut encoder = gpu.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("shader chain encoder"),
+        });
+
+        for pass in &self.passes {
+            let (Some(pipeline), Some(bind_group)) = (&pass.pipeline, &pass.bind_group) else {
+                continue;
+            };
+
+            let uniforms = PassUniforms {
+                source_size: size,
+                frame_time: frame_time as f32,
+                frame_count: self.frame_count,
+            };
+            let mut buffer = UniformBuffer::new(Vec::new());
+            buffer.write(&uniforms).unwrap();
+            gpu_queue.write_buffer(&pass.uniform_buffer, 0, &buffer.into_inner());
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("shader pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &pass.output,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..6, 0..1); // two triangles covering the target
+        }
+
+        gpu_queue.submit(Some(encoder.finish()));
+        self.frame_count = self.frame_count.wrapping_add(1);
+    }
+
+    /// Links each enabled pass to the output of the previous enabled one.
+    fn rebind(&mut self, gpu: &wgpu::Device) {
+        let mut input: Option<usize> = None;
+        for i in 0..self.passes.len() {
+            if self.passes[i].pipeline.is_none() {
+                self.passes[i].bind_group = None;
+                continue;
+            }
+
+            let source = input.map_or(&self.source, |j| &self.passes[j].output);
+            let bind_group = gpu.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("shader pass bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.passes[i].uniform_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+            self.passes[i].bind_group = Some(bind_group);
+            input = Some(i);
+        }
+    }
+}
+
+/// Shader capabilities the device supports, so a pass that passes validation also compiles.
+fn capabilities(gpu: &wgpu::Device) -> naga::valid::Capabilities {
+    use naga::valid::Capabilities;
+
+    let features = gpu.features();
+    let mut capabilities = Capabilities::default();
+    for (feature, capability) in [
+        (wgpu::Features::SHADER_F64, Capabilities::FLOAT64),
+        (wgpu::Features::SHADER_F16, Capabilities::SHADER_FLOAT16),
+        (wgpu::Features::SHADER_INT64, Capabilities::SHADER_INT64),
+        (wgpu::Features::PUSH_CONSTANTS, Capabilities::PUSH_CONSTANT),
+        (
+            wgpu::Features::SHADER_PRIMITIVE_INDEX,
+            Capabilities::PRIMITIVE_INDEX,
+        ),
+        (
+            wgpu::Features::DUAL_SOURCE_BLENDING,
+            Capabilities::DUAL_SOURCE_BLENDING,
+        ),
+    ] {
+        capabilities.set(capability, features.contains(feature));
+    }
+    capabilities
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Loads and validates one pass; errors are logged so a broken file never takes down playback.
+fn compile_pass(
+    gpu: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    path: &Path,
+) -> Option<wgpu::RenderPipeline> {
+    let user_source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            error!("failed to read shader {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let source = format!("{PRELUDE}\n{user_source}");
+
+    // validate up front because wgpu treats shader errors as fatal
+    let module = match naga::front::wgsl::parse_str(&source) {
+        Ok(module) => module,
+        Err(e) => {
+            error!(
+                "failed to parse shader {}:\n{}",
+                path.display(),
+                e.emit_to_string(&source)
+            );
+            return None;
+        }
+    };
+    if let Err(e) =
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), capabilities(gpu))
+            .validate(&module)
+    {
+        error!(
+            "invalid shader {}:\n{}",
+            path.display(),
+            e.emit_to_string(&source)
+        );
+        return None;
+    }
+    if !module
+        .entry_points
+        .iter()
+        .any(|ep| ep.name == "fs_main" && ep.stage == naga::ShaderStage::Fragment)
+    {
+        error!(
+            "shader {} does not define @fragment fn fs_main",
+            path.display()
+        );
+        return None;
+    }
+
+    let shader = gpu.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("user shader pass"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let pipeline = gpu.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("user shader pass pipeline"),
+        cache: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: INTERMEDIATE_FORMAT,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    });
+    info!("loaded shader pass {}", path.display());
+    Some(pipeline)
+}
@@ -0,0 +1,39 @@
+// Prepended to every user shader pass.
+// A pass only needs to define:
+//   @fragment fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>
+
+struct PassUniforms {
+    source_size: vec2<f32>, // size of source_tex and of this pass's render target in pixels
+    frame_time: f32,        // presentation time of the current frame in seconds
+    frame_count: u32,       // number of frames processed by the chain
+};
+
+@group(0) @binding(0) var source_tex: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+@group(0) @binding(2) var<uniform> params: PassUniforms;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) idx: u32) -> VertexOutput {
+    // fullscreen quad, uv (0, 0) is the top-left corner of the source
+    var quad = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0)
+    );
+    let uv = quad[idx];
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// ---- user pass ----
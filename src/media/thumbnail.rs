@@ -0,0 +1,134 @@
+use std::{collections::HashMap, sync::Arc, thread};
+
+use ffmpeg_next::{self as ffmpeg, util::frame::Video as FrameVideo};
+use parking_lot::Mutex;
+use tracing::warn;
+
+use crate::media::decoder::VideoDecoder;
+
+const THUMBNAIL_WIDTH: u32 = 192;
+// the timeline is split into this many slots at most, each with one preview
+const MAX_SLOTS: f64 = 300.0;
+// slots are never shorter than this, so short clips do not decode every frame
+const MIN_SLOT: f64 = 1.0; // seconds
+
+/// A small RGBA preview picture.
+pub struct Thumbnail {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+/// Decodes seek bar previews on a background thread with its own decoder, so playback is
+/// never held up.
+pub struct Thumbnailer {
+    slot: f64,
+    requests: flume::Sender<u64>,
+    cache: Arc<Mutex<HashMap<u64, Arc<Thumbnail>>>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for Thumbnailer {
+    fn drop(&mut self) {
+        // disconnect the request channel so the thread exits after the picture in progress
+        self.requests = flume::bounded(0).0;
+        if let Some(handle) = self.thread.take() {
+            handle.join().unwrap();
+        }
+    }
+}
+
+impl Thumbnailer {
+    /// Previews for the video of `path`, which lasts `duration` seconds.
+    pub fn new(path: &str, duration: f64) -> Self {
+        let slot = (duration / MAX_SLOTS).max(MIN_SLOT);
+        let (requests, rx_requests) = flume::unbounded::<u64>();
+        let cache = Arc::new(Mutex::new(HashMap::new()));
+        let thread = {
+            let path = path.to_string();
+            let cache = cache.clone();
+            thread::spawn(move || {
+                let Some(mut decoder) = VideoDecoder::new(&path) else {
+                    return;
+                };
+                while let Ok(mut index) = rx_requests.recv() {
+                    // only the latest hover position matters
+                    while let Ok(newer) = rx_requests.try_recv() {
+                        index = newer;
+                    }
+                    if cache.lock().contains_key(&index) {
+                        continue;
+                    }
+                    if let Some(thumbnail) = decode(&mut decoder, index as f64 * slot) {
+                        cache.lock().insert(index, Arc::new(thumbnail));
+                    }
+                }
+            })
+        };
+        Self {
+            slot,
+            requests,
+            cache,
+            thread: Some(thread),
+        }
+    }
+
+    /// The preview for `seconds`, or the closest one decoded so far while it is prepared.
+    pub fn thumbnail(&self, seconds: f64) -> Option<Arc<Thumbnail>> {
+        let index = (seconds.max(0.0) / self.slot).round() as u64;
+        let cache = self.cache.lock();
+        if let Some(thumbnail) = cache.get(&index) {
+            return Some(thumbnail.clone());
+        }
+        let _ = self.requests.send(index);
+        cache
+            .iter()
+            .min_by_key(|&(&other, _)| other.abs_diff(index))
+            .map(|(_, thumbnail)| thumbnail.clone())
+    }
+}
+
+/// Decodes the picture at `seconds`, scaled down to [`THUMBNAIL_WIDTH`].
+fn decode(decoder: &mut VideoDecoder, seconds: f64) -> Option<Thumbnail> {
+    if let Err(e) = decoder.seek(seconds) {
+        warn!(
+            "failed to seek thumbnail decoder to {:.3}s: {:?}",
+            seconds, e
+        );
+        return None;
+    }
+    // the keyframe the seek lands on is close enough for a preview and much faster than
+    // decoding up to the exact time
+    let frame = decoder.next()?;
+
+    let width = THUMBNAIL_WIDTH.min(frame.width()).max(1);
+    let height = ((frame.height() as f64 * width as f64 / frame.width() as f64) as u32).max(1);
+    let mut scaler = ffmpeg::software::scaling::Context::get(
+        frame.format(),
+        frame.width(),
+        frame.height(),
+        ffmpeg::format::Pixel::RGBA,
+        width,
+        height,
+        ffmpeg::software::scaling::Flags::BILINEAR,
+    )
+    .ok()?;
+    let mut rgba_frame = FrameVideo::empty();
+    scaler.run(&frame, &mut rgba_frame).ok()?;
+
+    // rows may be padded for alignment, so copy them one by one
+    let stride = rgba_frame.stride(0);
+    let row = width as usize * 4;
+    let rgba = rgba_frame
+        .data(0)
+        .chunks(stride)
+        .take(height as usize)
+        .flat_map(|line| &line[..row])
+        .copied()
+        .collect();
+    Some(Thumbnail {
+        width,
+        height,
+        rgba,
+    })
+}
@@ -1,5 +1,10 @@
 struct VideoUniforms {
     rect: vec4<f32>, // x, y, w, h (归一化屏幕坐标)
+    picture: vec4<f32>, // 亮度偏移, 对比度, 饱和度, gamma 系数
+    source_size: vec2<f32>, // 源纹理尺寸 (像素)
+    output_size: vec2<f32>, // 屏幕上的目标尺寸 (像素)
+    hue: f32, // 色相旋转 (弧度)
+    sharpness: f32, // CAS 锐化强度 [0, 1]
 };
 
 @group(0) @binding(0) var video_tex: texture_2d<f32>;
@@ -46,9 +51,190 @@ fn vs_main(@builtin(vertex_index) idx: u32) -> VertexOutput {
     return out;
 }
 
+// 在感知 (gamma 编码) 空间中应用画面调节, 使调节步长在视觉上均匀
+fn adjust_picture(linear: vec3<f32>) -> vec3<f32> {
+    var c = pow(max(linear, vec3<f32>(0.0)), vec3<f32>(1.0 / 2.2));
+
+    // 亮度与对比度
+    c = c + vec3<f32>(uniforms.picture.x);
+    c = (c - vec3<f32>(0.5)) * uniforms.picture.y + vec3<f32>(0.5);
+
+    // 饱和度: 向亮度值插值
+    let luma = dot(c, vec3<f32>(0.2126, 0.7152, 0.0722));
+    c = mix(vec3<f32>(luma), c, uniforms.picture.z);
+
+    // 色相: 在 YIQ 空间中旋转色度分量
+    let y = dot(c, vec3<f32>(0.299, 0.587, 0.114));
+    let i = dot(c, vec3<f32>(0.596, -0.274, -0.322));
+    let q = dot(c, vec3<f32>(0.211, -0.523, 0.312));
+    let cos_h = cos(uniforms.hue);
+    let sin_h = sin(uniforms.hue);
+    let i2 = i * cos_h - q * sin_h;
+    let q2 = i * sin_h + q * cos_h;
+    c = vec3<f32>(
+        y + 0.956 * i2 + 0.621 * q2,
+        y - 0.272 * i2 - 0.647 * q2,
+        y - 1.106 * i2 + 1.703 * q2
+    );
+
+    // gamma
+    c = pow(max(c, vec3<f32>(0.0)), vec3<f32>(1.0 / uniforms.picture.w));
+
+    return pow(clamp(c, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(2.2));
+}
+
+// 以纹素坐标采样 (纹素中心位于 i + 0.5)
+fn texel(p: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(video_tex, video_sampler, p / uniforms.source_size, 0.0);
+}
+
+// 每个方向上源纹素与目标像素之比, 大于 1 表示缩小
+fn scale_ratio() -> vec2<f32> {
+    return uniforms.source_size / max(uniforms.output_size, vec2<f32>(1.0));
+}
+
+// 缩小时的抗锯齿: 在目标像素覆盖的区域内均匀取若干双线性样本求平均
+// 每个双线性样本覆盖 2x2 纹素, 因此 4K 缩到小窗口也只需少量采样
+fn sample_area(uv: vec2<f32>) -> vec4<f32> {
+    let ratio = scale_ratio();
+    let taps = clamp(ceil(ratio / 2.0), vec2<f32>(1.0), vec2<f32>(8.0));
+    let footprint = ratio / uniforms.source_size;
+    var sum = vec4<f32>(0.0);
+    for (var j = 0.0; j < taps.y; j += 1.0) {
+        for (var i = 0.0; i < taps.x; i += 1.0) {
+            let offset = (vec2<f32>(i, j) + 0.5) / taps - 0.5;
+            sum += textureSampleLevel(video_tex, video_sampler, uv + offset * footprint, 0.0);
+        }
+    }
+    return sum / (taps.x * taps.y);
+}
+
+fn is_downscaling() -> bool {
+    let ratio = scale_ratio();
+    return max(ratio.x, ratio.y) > 1.0;
+}
+
+// Mitchell-Netravali 双三次核, (B, C) 决定锐度与振铃
+fn bc_cubic(x: f32, b: f32, c: f32) -> f32 {
+    let ax = abs(x);
+    let ax2 = ax * ax;
+    let ax3 = ax2 * ax;
+    if ax < 1.0 {
+        return ((12.0 - 9.0 * b - 6.0 * c) * ax3 + (-18.0 + 12.0 * b + 6.0 * c) * ax2 + (6.0 - 2.0 * b)) / 6.0;
+    }
+    if ax < 2.0 {
+        return ((-b - 6.0 * c) * ax3 + (6.0 * b + 30.0 * c) * ax2 + (-12.0 * b - 48.0 * c) * ax + (8.0 * b + 24.0 * c)) / 6.0;
+    }
+    return 0.0;
+}
+
+fn sample_cubic(uv: vec2<f32>, b: f32, c: f32) -> vec4<f32> {
+    if is_downscaling() {
+        return sample_area(uv);
+    }
+    let pos = uv * uniforms.source_size - 0.5;
+    let base = floor(pos);
+    let f = pos - base;
+    var sum = vec4<f32>(0.0);
+    var weight_sum = 0.0;
+    for (var j = -1; j <= 2; j++) {
+        let wy = bc_cubic(f32(j) - f.y, b, c);
+        for (var i = -1; i <= 2; i++) {
+            let w = bc_cubic(f32(i) - f.x, b, c) * wy;
+            sum += texel(base + vec2<f32>(f32(i), f32(j)) + 0.5) * w;
+            weight_sum += w;
+        }
+    }
+    return sum / weight_sum;
+}
+
+fn sinc(x: f32) -> f32 {
+    if abs(x) < 1e-5 {
+        return 1.0;
+    }
+    let px = 3.14159265 * x;
+    return sin(px) / px;
+}
+
+fn lanczos3(x: f32) -> f32 {
+    if abs(x) >= 3.0 {
+        return 0.0;
+    }
+    return sinc(x) * sinc(x / 3.0);
+}
+
+fn sample_lanczos(uv: vec2<f32>) -> vec4<f32> {
+    if is_downscaling() {
+        return sample_area(uv);
+    }
+    let pos = uv * uniforms.source_size - 0.5;
+    let base = floor(pos);
+    let f = pos - base;
+    var sum = vec4<f32>(0.0);
+    var weight_sum = 0.0;
+    for (var j = -2; j <= 3; j++) {
+        let wy = lanczos3(f32(j) - f.y);
+        for (var i = -2; i <= 3; i++) {
+            let w = lanczos3(f32(i) - f.x) * wy;
+            sum += texel(base + vec2<f32>(f32(i), f32(j)) + 0.5) * w;
+            weight_sum += w;
+        }
+    }
+    // 负瓣可能产生越界值, 裁剪以避免光晕
+    return clamp(sum / weight_sum, vec4<f32>(0.0), vec4<f32>(1.0));
+}
+
+// 对比度自适应锐化 (CAS): 双线性放大后按局部对比度调整锐化量, 避免过冲
+fn sample_cas(uv: vec2<f32>) -> vec4<f32> {
+    if is_downscaling() {
+        return sample_area(uv);
+    }
+    let px = 1.0 / uniforms.source_size;
+    let e = textureSampleLevel(video_tex, video_sampler, uv, 0.0);
+    let b = textureSampleLevel(video_tex, video_sampler, uv + vec2<f32>(0.0, -px.y), 0.0).rgb;
+    let d = textureSampleLevel(video_tex, video_sampler, uv + vec2<f32>(-px.x, 0.0), 0.0).rgb;
+    let f = textureSampleLevel(video_tex, video_sampler, uv + vec2<f32>(px.x, 0.0), 0.0).rgb;
+    let h = textureSampleLevel(video_tex, video_sampler, uv + vec2<f32>(0.0, px.y), 0.0).rgb;
+
+    let mn = min(min(min(b, d), min(f, h)), e.rgb);
+    let mx = max(max(max(b, d), max(f, h)), e.rgb);
+    let amp = sqrt(clamp(min(mn, vec3<f32>(1.0) - mx) / max(mx, vec3<f32>(1e-5)), vec3<f32>(0.0), vec3<f32>(1.0)));
+    let peak = -1.0 / mix(8.0, 5.0, clamp(uniforms.sharpness, 0.0, 1.0));
+    let w = amp * peak;
+    let rgb = ((b + d + f + h) * w + e.rgb) / (vec3<f32>(1.0) + 4.0 * w);
+    return vec4<f32>(clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0)), e.a);
+}
+
+fn finish(color: vec4<f32>) -> vec4<f32> {
+    return vec4<f32>(adjust_picture(color.rgb), color.a);
+}
+
 @fragment
 // 输入参数改为我们定义的 VertexOutput 结构体
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+fn fs_bilinear(in: VertexOutput) -> @location(0) vec4<f32> {
     // 直接使用从顶点着色器传递并插值好的 UV 坐标
-    return textureSample(video_tex, video_sampler, in.uv);
+    if is_downscaling() {
+        return finish(sample_area(in.uv));
+    }
+    return finish(textureSampleLevel(video_tex, video_sampler, in.uv, 0.0));
+}
+
+@fragment
+fn fs_catmull_rom(in: VertexOutput) -> @location(0) vec4<f32> {
+    return finish(sample_cubic(in.uv, 0.0, 0.5));
+}
+
+@fragment
+fn fs_mitchell(in: VertexOutput) -> @location(0) vec4<f32> {
+    return finish(sample_cubic(in.uv, 1.0 / 3.0, 1.0 / 3.0));
+}
+
+@fragment
+fn fs_lanczos(in: VertexOutput) -> @location(0) vec4<f32> {
+    return finish(sample_lanczos(in.uv));
+}
+
+@fragment
+fn fs_cas(in: VertexOutput) -> @location(0) vec4<f32> {
+    return finish(sample_cas(in.uv));
 }
@@ -0,0 +1,505 @@
+use std::{collections::HashMap, f32::consts::PI, sync::Arc, time::Instant};
+
+use encase::{ShaderType, UniformBuffer};
+use glam::Vec4;
+use tessera_ui::{DrawCommand, DrawablePipeline, wgpu};
+use uuid::Uuid;
+
+use crate::{
+    audio::tap::PcmTap,
+    media::{clock::GlobalClock, cover::CoverArt},
+};
+
+// size of the column array in visualizer.wgsl
+const MAX_COLUMNS: usize = 256;
+const SPECTRUM_BARS: usize = 64;
+const FFT_SIZE: usize = 2048;
+// frequency range spread over the bars on a log scale
+const SPECTRUM_LOW: f32 = 40.0; // Hz
+const SPECTRUM_HIGH: f32 = 16000.0; // Hz
+// bars cover this many dB below full scale
+const SPECTRUM_RANGE: f32 = 70.0;
+// bars fall at this fraction of the full height per second instead of flickering
+const SPECTRUM_FALL: f32 = 1.5;
+const WAVEFORM_SECONDS: f64 = 2.0;
+const WAVEFORM_COLUMNS: usize = 256;
+// the trace starts at a rising zero crossing found in the first part of the window, so
+// periodic sounds stand still
+const SCOPE_SEARCH: usize = 2048; // samples
+const SCOPE_LENGTH: usize = 1024; // samples
+const SCOPE_COLUMNS: usize = 256;
+const SCOPE_THICKNESS: f32 = 0.006;
+// brightness of the cover art behind a visualization
+const COVER_DIM: f32 = 0.35;
+
+/// What the video area shows for files without video.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Visualization {
+    /// Embedded cover art or folder.jpg
+    #[default]
+    Cover,
+    /// Frequency bars
+    Spectrum,
+    /// Scrolling amplitude of the last two seconds
+    Waveform,
+    /// Triggered trace of the current waveform
+    Oscilloscope,
+}
+
+impl Visualization {
+    pub const ALL: [Visualization; 4] = [
+        Visualization::Cover,
+        Visualization::Spectrum,
+        Visualization::Waveform,
+        Visualization::Oscilloscope,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Visualization::Cover => "Cover art",
+            Visualization::Spectrum => "Spectrum",
+            Visualization::Waveform => "Waveform",
+            Visualization::Oscilloscope => "Oscilloscope",
+        }
+    }
+
+    /// The next mode in [`Visualization::ALL`], wrapping around.
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&mode| mode == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn color(self) -> Vec4 {
+        match self {
+            Visualization::Cover => Vec4::ZERO,
+            Visualization::Spectrum => Vec4::new(0.30, 0.60, 1.0, 1.0),
+            Visualization::Waveform => Vec4::new(0.40, 0.90, 0.70, 1.0),
+            Visualization::Oscilloscope => Vec4::new(0.45, 1.0, 0.45, 1.0),
+        }
+    }
+}
+
+/// In-place radix-2 FFT; the length must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len *= 2;
+    }
+}
+
+/// Bar heights in [0, 1] for log-spaced bands of the last [`FFT_SIZE`] samples.
+fn spectrum(samples: &[f32], sample_rate: u32) -> Vec<f32> {
+    let window: Vec<f32> = (0..FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos())
+        .collect();
+    let mut re: Vec<f32> = samples.iter().zip(&window).map(|(s, w)| s * w).collect();
+    let mut im = vec![0.0; FFT_SIZE];
+    fft(&mut re, &mut im);
+
+    // scale so a full-scale sine reads 0 dB
+    let norm = 2.0 / window.iter().sum::<f32>();
+    let bin_hz = sample_rate as f32 / FFT_SIZE as f32;
+    let high = SPECTRUM_HIGH.min(sample_rate as f32 / 2.0);
+    let ratio = high / SPECTRUM_LOW;
+    (0..SPECTRUM_BARS)
+        .map(|bar| {
+            let low = SPECTRUM_LOW * ratio.powf(bar as f32 / SPECTRUM_BARS as f32);
+            let high = SPECTRUM_LOW * ratio.powf((bar + 1) as f32 / SPECTRUM_BARS as f32);
+            let first = ((low / bin_hz) as usize).clamp(1, FFT_SIZE / 2 - 1);
+            let last = ((high / bin_hz).ceil() as usize).clamp(first + 1, FFT_SIZE / 2);
+            let peak = (first..last)
+                .map(|bin| (re[bin] * re[bin] + im[bin] * im[bin]).sqrt() * norm)
+                .fold(0.0, f32::max);
+            let db = 20.0 * peak.max(1e-9).log10();
+            ((db + SPECTRUM_RANGE) / SPECTRUM_RANGE).clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+/// Sample value in [-1, 1] to a height in [0, 1] with a small margin.
+fn sample_height(sample: f32) -> f32 {
+    0.5 + sample.clamp(-1.0, 1.0) * 0.45
+}
+
+/// Minimum and maximum of each slice of the window, oldest on the left.
+fn waveform(samples: &[f32]) -> Vec<(f32, f32)> {
+    let per_column = (samples.len() / WAVEFORM_COLUMNS).max(1);
+    samples
+        .chunks(per_column)
+        .take(WAVEFORM_COLUMNS)
+        .map(|chunk| {
+            let (low, high) = chunk.iter().fold((f32::MAX, f32::MIN), |(low, high), &s| {
+                (low.min(s), high.max(s))
+            });
+            (sample_height(low), sample_height(high))
+        })
+        .collect()
+}
+
+/// A line through [`SCOPE_LENGTH`] samples starting at the first rising zero crossing.
+fn oscilloscope(samples: &[f32]) -> Vec<(f32, f32)> {
+    let trigger = (1..SCOPE_SEARCH.min(samples.len()))
+        .find(|&i| samples[i - 1] < 0.0 && samples[i] >= 0.0)
+        .unwrap_or(0);
+    let trace = &samples[trigger..(trigger + SCOPE_LENGTH).min(samples.len())];
+    let per_column = (trace.len() / SCOPE_COLUMNS).max(1);
+    (0..SCOPE_COLUMNS)
+        .filter_map(|column| {
+            // include the first sample of the next column so the segments join up
+            let start = column * per_column;
+            let points = trace.get(start..(start + per_column + 1).min(trace.len()))?;
+            let (low, high) = points.iter().fold((f32::MAX, f32::MIN), |(low, high), &s| {
+                (low.min(s), high.max(s))
+            });
+            Some((
+                sample_height(low) - SCOPE_THICKNESS,
+                sample_height(high) + SCOPE_THICKNESS,
+            ))
+        })
+        .collect()
+}
+
+#[derive(ShaderType)]
+struct VisualizerUniforms {
+    rect: Vec4,
+    cover_rect: Vec4,
+    color: Vec4,
+    cover_dim: f32,
+    column_count: u32,
+    gap: f32,
+    columns: [Vec4; MAX_COLUMNS / 2],
+}
+
+struct VisualizerTarget {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    // cover art size in pixels, None when there is none
+    cover_size: Option<(u32, u32)>,
+    // displayed spectrum, falling smoothly towards the measured one
+    bars: Vec<f32>,
+    last_draw: Instant,
+}
+
+impl VisualizerTarget {
+    fn new(
+        gpu: &wgpu::Device,
+        gpu_queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        cover: Option<&CoverArt>,
+    ) -> Self {
+        // a transparent pixel stands in when there is no art, so the bindings stay the same
+        let (width, height, pixels) = match cover {
+            Some(cover) => (cover.width, cover.height, cover.rgba.as_slice()),
+            None => (1, 1, &[0u8; 4][..]),
+        };
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = gpu.create_texture(&wgpu::TextureDescriptor {
+            label: Some("cover art texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        gpu_queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            pixels,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: None,
+            },
+            size,
+        );
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = gpu.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = gpu.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("visualizer bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let uniform_buffer = gpu.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("visualizer uniform buffer"),
+            size: VisualizerUniforms::min_size().get(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = gpu.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("visualizer bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = gpu.create_shader_module(wgpu::include_wgsl!("visualizer.wgsl"));
+        let pipeline_layout = gpu.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("visualizer pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = gpu.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("visualizer pipeline"),
+            cache: None,
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            uniform_buffer,
+            bind_group,
+            cover_size: cover.map(|cover| (cover.width, cover.height)),
+            bars: vec![0.0; SPECTRUM_BARS],
+            last_draw: Instant::now(),
+        }
+    }
+
+    /// Column ranges for the current audio position, smoothing the spectrum between frames.
+    fn columns(&mut self, cmd: &VisualizerCommand) -> Vec<(f32, f32)> {
+        let elapsed = self.last_draw.elapsed().as_secs_f32();
+        self.last_draw = Instant::now();
+        let now = cmd.clock.now();
+        match cmd.visualization {
+            Visualization::Cover => Vec::new(),
+            Visualization::Spectrum => {
+                let (samples, sample_rate) = cmd.tap.window(now, FFT_SIZE);
+                let measured = spectrum(&samples, sample_rate);
+                for (bar, level) in self.bars.iter_mut().zip(measured) {
+                    *bar = level.max(*bar - SPECTRUM_FALL * elapsed);
+                }
+                self.bars.iter().map(|&bar| (0.0, bar)).collect()
+            }
+            Visualization::Waveform => {
+                let (_, sample_rate) = cmd.tap.window(now, 0);
+                let len = (WAVEFORM_SECONDS * sample_rate as f64) as usize;
+                waveform(&cmd.tap.window(now, len).0)
+            }
+            Visualization::Oscilloscope => {
+                // look back far enough that a whole trace follows the latest trigger point
+                let (samples, _) = cmd.tap.window(now, SCOPE_SEARCH + SCOPE_LENGTH);
+                oscilloscope(&samples)
+            }
+        }
+    }
+
+    /// Cover art placement inside a quad of `width` x `height` pixels, keeping its aspect.
+    fn cover_rect(&self, width: f32, height: f32) -> Vec4 {
+        let Some((cover_width, cover_height)) = self.cover_size else {
+            // outside the quad, so nothing is sampled
+            return Vec4::new(2.0, 2.0, 1.0, 1.0);
+        };
+        let scale = (width / cover_width as f32).min(height / cover_height as f32);
+        let w = cover_width as f32 * scale / width;
+        let h = cover_height as f32 * scale / height;
+        Vec4::new((1.0 - w) / 2.0, (1.0 - h) / 2.0, w, h)
+    }
+}
+
+pub struct VisualizerPipeline {
+    targets: HashMap<Uuid, VisualizerTarget>,
+    sample_count: u32,
+}
+
+impl VisualizerPipeline {
+    pub fn new(sample_count: u32) -> Self {
+        Self {
+            targets: HashMap::new(),
+            sample_count,
+        }
+    }
+}
+
+/// Draws the cover art and visualization of an audio-only file.
+#[derive(Clone)]
+pub struct VisualizerCommand {
+    pub id: Uuid,
+    pub tap: Arc<PcmTap>,
+    pub clock: GlobalClock,
+    pub cover: Option<Arc<CoverArt>>,
+    pub visualization: Visualization,
+}
+
+impl PartialEq for VisualizerCommand {
+    fn eq(&self, other: &Self) -> bool {
+        // like the video, the picture only stands still while paused
+        self.id == other.id && self.clock.is_paused() && self.visualization == other.visualization
+    }
+}
+
+impl DrawCommand for VisualizerCommand {}
+
+impl DrawablePipeline<VisualizerCommand> for VisualizerPipeline {
+    fn draw(
+        &mut self,
+        gpu: &tessera_ui::wgpu::Device,
+        gpu_queue: &tessera_ui::wgpu::Queue,
+        config: &tessera_ui::wgpu::SurfaceConfiguration,
+        render_pass: &mut tessera_ui::wgpu::RenderPass<'_>,
+        commands: &[(
+            &VisualizerCommand,
+            tessera_ui::PxSize,
+            tessera_ui::PxPosition,
+        )],
+        _scene_texture_view: &tessera_ui::wgpu::TextureView,
+        _clip_rect: Option<tessera_ui::PxRect>,
+    ) {
+        for (cmd, size, pos) in commands {
+            let target = self.targets.entry(cmd.id).or_insert_with(|| {
+                VisualizerTarget::new(
+                    gpu,
+                    gpu_queue,
+                    config,
+                    self.sample_count,
+                    cmd.cover.as_deref(),
+                )
+            });
+
+            let columns = target.columns(cmd);
+            let mut packed = [Vec4::ZERO; MAX_COLUMNS / 2];
+            for (index, &(low, high)) in columns.iter().take(MAX_COLUMNS).enumerate() {
+                let pair = &mut packed[index / 2];
+                if index % 2 == 0 {
+                    pair.x = low;
+                    pair.y = high;
+                } else {
+                    pair.z = low;
+                    pair.w = high;
+                }
+            }
+            let cover_dim = if cmd.visualization == Visualization::Cover {
+                1.0
+            } else {
+                COVER_DIM
+            };
+            let uniforms = VisualizerUniforms {
+                rect: Vec4::new(
+                    pos.x.0 as f32 / config.width as f32,
+                    pos.y.0 as f32 / config.height as f32,
+                    size.width.0 as f32 / config.width as f32,
+                    size.height.0 as f32 / config.height as f32,
+                ),
+                cover_rect: target.cover_rect(size.width.0 as f32, size.height.0 as f32),
+                color: cmd.visualization.color(),
+                cover_dim,
+                column_count: columns.len().min(MAX_COLUMNS) as u32,
+                // separate the spectrum bars, while waveforms stay continuous
+                gap: if cmd.visualization == Visualization::Spectrum {
+                    0.25
+                } else {
+                    0.0
+                },
+                columns: packed,
+            };
+            let mut buffer = UniformBuffer::new(Vec::new());
+            buffer.write(&uniforms).unwrap();
+            gpu_queue.write_buffer(&target.uniform_buffer, 0, &buffer.into_inner());
+
+            render_pass.set_pipeline(&target.pipeline);
+            render_pass.set_bind_group(0, &target.bind_group, &[]);
+            render_pass.draw(0..6, 0..1);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+struct VisualizerUniforms {
+    rect: vec4<f32>,       // x, y, w, h in screen-normalized coordinates
+    cover_rect: vec4<f32>, // cover art placement inside the quad, in uv
+    color: vec4<f32>,      // color of the visualization
+    cover_dim: f32,        // brightness of the cover art, lowered behind a visualization
+    column_count: u32,     // number of columns in use, 0 shows only the cover art
+    gap: f32,              // fraction of each column left empty, separating spectrum bars
+    // (bottom, top) of each column in [0, 1] measured from the bottom, two columns per vec4
+    columns: array<vec4<f32>, 128>,
+};
+
+@group(0) @binding(0) var cover_tex: texture_2d<f32>;
+@group(0) @binding(1) var cover_sampler: sampler;
+@group(0) @binding(2) var<uniform> uniforms: VisualizerUniforms;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) idx: u32) -> VertexOutput {
+    var quad = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0)
+    );
+    let uv = quad[idx];
+    let screen_pos = uniforms.rect.xy + uv * uniforms.rect.zw;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(screen_pos.x * 2.0 - 1.0, 1.0 - screen_pos.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = vec4<f32>(0.0);
+
+    let cover_uv = (in.uv - uniforms.cover_rect.xy) / uniforms.cover_rect.zw;
+    if all(cover_uv >= vec2<f32>(0.0)) && all(cover_uv <= vec2<f32>(1.0)) {
+        let art = textureSampleLevel(cover_tex, cover_sampler, cover_uv, 0.0);
+        color = vec4<f32>(art.rgb * uniforms.cover_dim, art.a);
+    }
+
+    if uniforms.column_count > 0u {
+        let position = in.uv.x * f32(uniforms.column_count);
+        let column = min(u32(position), uniforms.column_count - 1u);
+        let within = fract(position);
+        let pair = uniforms.columns[column / 2u];
+        let range = select(pair.xy, pair.zw, column % 2u == 1u);
+        let y = 1.0 - in.uv.y;
+        let half_gap = uniforms.gap * 0.5;
+        if y >= range.x && y <= range.y && within >= half_gap && within <= 1.0 - half_gap {
+            // brighter towards the top so loud passages stand out
+            let shade = mix(0.55, 1.0, y);
+            color = vec4<f32>(mix(color.rgb, uniforms.color.rgb * shade, uniforms.color.a), 1.0);
+        }
+    }
+
+    return color;
+}
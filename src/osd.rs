@@ -0,0 +1,195 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use parking_lot::RwLock;
+use tessera_ui::{Color, DimensionValue, Dp, tessera};
+use tessera_ui_basic_components::{
+    alignment::{Alignment, CrossAxisAlignment},
+    boxed::{BoxedArgs, boxed},
+    column::{ColumnArgs, column},
+    shape_def::Shape,
+    surface::{SurfaceArgs, surface},
+    text::{TextArgs, text},
+};
+
+use crate::media::{VideoPlayerState, seek_bar::format_time};
+
+const PROGRESS_WIDTH: f64 = 240.0; // dp
+const PROGRESS_HEIGHT: f64 = 4.0; // dp
+
+/// Corner or edge of the window where OSD messages appear.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OsdPosition {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Center,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl OsdPosition {
+    fn alignment(self) -> Alignment {
+        match self {
+            OsdPosition::TopLeft => Alignment::TopStart,
+            OsdPosition::Top => Alignment::TopCenter,
+            OsdPosition::TopRight => Alignment::TopEnd,
+            OsdPosition::Center => Alignment::Center,
+            OsdPosition::BottomLeft => Alignment::BottomStart,
+            OsdPosition::Bottom => Alignment::BottomCenter,
+            OsdPosition::BottomRight => Alignment::BottomEnd,
+        }
+    }
+}
+
+/// How OSD messages look and how long they stay.
+#[derive(Clone, Copy, Debug)]
+pub struct OsdOptions {
+    pub duration: Duration,
+    pub position: OsdPosition,
+    /// Text size in dp
+    pub font_size: f64,
+}
+
+/// Transient feedback shown over the video, such as "Volume 80%" or the position after a seek.
+pub struct Osd {
+    options: OsdOptions,
+    message: RwLock<Option<(String, Instant)>>,
+    // set by seeks; the progress overlay is shown until this long after
+    progress: RwLock<Option<Instant>>,
+}
+
+impl Osd {
+    pub fn new(options: OsdOptions) -> Self {
+        Self {
+            options,
+            message: RwLock::new(None),
+            progress: RwLock::new(None),
+        }
+    }
+
+    /// Shows `message`, replacing the one on screen.
+    pub fn show(&self, message: impl Into<String>) {
+        *self.message.write() = Some((message.into(), Instant::now()));
+    }
+
+    /// Shows the playback position and a progress bar under the message.
+    pub fn show_progress(&self) {
+        *self.progress.write() = Some(Instant::now());
+    }
+
+    fn is_recent(&self, since: Instant) -> bool {
+        since.elapsed() < self.options.duration
+    }
+}
+
+/// The current OSD message and progress overlay, if any.
+#[tessera]
+pub fn osd_layer(osd: Arc<Osd>, player: Arc<RwLock<VideoPlayerState>>) {
+    let message = osd
+        .message
+        .read()
+        .as_ref()
+        .filter(|(_, shown)| osd.is_recent(*shown))
+        .map(|(message, _)| message.clone());
+    let progress = osd
+        .progress
+        .read()
+        .filter(|&shown| osd.is_recent(shown))
+        .map(|_| {
+            let player = player.read();
+            let position = player.position();
+            let duration = player.duration();
+            let label = match duration {
+                Some(duration) => format!("{} / {}", format_time(position), format_time(duration)),
+                None => format_time(position),
+            };
+            let fraction = duration
+                .filter(|&duration| duration > 0.0)
+                .map(|duration| (position / duration).clamp(0.0, 1.0) as f32);
+            (label, fraction)
+        });
+    if message.is_none() && progress.is_none() {
+        return;
+    }
+    let options = osd.options;
+
+    boxed(
+        BoxedArgs {
+            alignment: options.position.alignment(),
+            width: DimensionValue::FILLED,
+            height: DimensionValue::FILLED,
+        },
+        move |scope| {
+            scope.child(move || {
+                surface(
+                    SurfaceArgs {
+                        style: Color::BLACK.with_alpha(0.5).into(),
+                        shape: Shape::rounded_rectangle(Dp(12.0)),
+                        padding: Dp(12.0),
+                        ..Default::default()
+                    },
+                    None,
+                    move || {
+                        column(
+                            ColumnArgs {
+                                cross_axis_alignment: CrossAxisAlignment::Start,
+                                ..Default::default()
+                            },
+                            move |scope| {
+                                if let Some(message) = message {
+                                    scope.child(move || osd_text(message, options.font_size));
+                                }
+                                if let Some((label, fraction)) = progress {
+                                    scope.child(move || osd_text(label, options.font_size));
+                                    if let Some(fraction) = fraction {
+                                        scope.child(move || progress_bar(fraction));
+                                    }
+                                }
+                            },
+                        );
+                    },
+                );
+            });
+        },
+    );
+}
+
+#[tessera]
+fn osd_text(content: String, size: f64) {
+    text(TextArgs {
+        text: content,
+        size: Dp(size),
+        color: Color::WHITE,
+        ..Default::default()
+    });
+}
+
+#[tessera]
+fn progress_bar(fraction: f32) {
+    surface(
+        SurfaceArgs {
+            width: Dp(PROGRESS_WIDTH).into(),
+            height: Dp(PROGRESS_HEIGHT).into(),
+            style: Color::WHITE.with_alpha(0.3).into(),
+            ..Default::default()
+        },
+        None,
+        move || {
+            surface(
+                SurfaceArgs {
+                    width: Dp(PROGRESS_WIDTH * fraction as f64).into(),
+                    height: DimensionValue::FILLED,
+                    style: Color::WHITE.into(),
+                    ..Default::default()
+                },
+                None,
+                || {},
+            );
+        },
+    );
+}
@@ -0,0 +1,125 @@
+use std::sync::{
+    Arc, OnceLock,
+    atomic::{AtomicBool, Ordering},
+};
+
+use tessera_ui::winit::{
+    dpi::PhysicalSize,
+    window::{Fullscreen, Window, WindowLevel},
+};
+use tracing::info;
+
+// a fitted window never covers more than this share of the monitor
+const MAX_SCREEN_SHARE: f64 = 0.9;
+
+/// How the window starts out.
+#[derive(Clone, Copy, Debug)]
+pub struct WindowOptions {
+    /// Window size relative to the video, e.g. 1.0 for its native size; `None` keeps the
+    /// default window size
+    pub scale: Option<f64>,
+    pub fullscreen: bool,
+    pub always_on_top: bool,
+}
+
+/// Access to the player window from the UI, available once the renderer has created it.
+pub struct WindowControl {
+    window: OnceLock<Arc<Window>>,
+    options: WindowOptions,
+    // size of the picture the window is fitted to, in pixels
+    video_size: Option<(u32, u32)>,
+    // winit cannot report the window level, so it is tracked here
+    always_on_top: AtomicBool,
+}
+
+impl WindowControl {
+    pub fn new(options: WindowOptions, video_size: Option<(u32, u32)>) -> Self {
+        Self {
+            window: OnceLock::new(),
+            options,
+            video_size,
+            always_on_top: AtomicBool::new(false),
+        }
+    }
+
+    /// Hands over the window created by the renderer and applies the startup options.
+    pub fn attach(&self, window: Arc<Window>) {
+        if self.window.set(window).is_err() {
+            return;
+        }
+        if let Some(scale) = self.options.scale {
+            self.set_scale(scale);
+        }
+        if self.options.always_on_top {
+            self.set_always_on_top(true);
+        }
+        if self.options.fullscreen {
+            self.toggle_fullscreen();
+        }
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.window
+            .get()
+            .is_some_and(|window| window.fullscreen().is_some())
+    }
+
+    /// Switches between a borderless fullscreen window on the current monitor and a normal one.
+    pub fn toggle_fullscreen(&self) {
+        let Some(window) = self.window.get() else {
+            return;
+        };
+        let fullscreen = if window.fullscreen().is_some() {
+            None
+        } else {
+            Some(Fullscreen::Borderless(None))
+        };
+        window.set_fullscreen(fullscreen);
+    }
+
+    /// Resizes the window to `scale` times the video size, shrunk to fit the monitor while
+    /// keeping the aspect ratio; leaves fullscreen first.
+    pub fn set_scale(&self, scale: f64) {
+        let (Some(window), Some((width, height))) = (self.window.get(), self.video_size) else {
+            return;
+        };
+        if scale <= 0.0 || scale.is_nan() {
+            return;
+        }
+        if window.fullscreen().is_some() {
+            window.set_fullscreen(None);
+        }
+        let mut size = (width as f64 * scale, height as f64 * scale);
+        if let Some(monitor) = window.current_monitor() {
+            let screen = monitor.size();
+            let fit = (screen.width as f64 * MAX_SCREEN_SHARE / size.0)
+                .min(screen.height as f64 * MAX_SCREEN_SHARE / size.1)
+                .min(1.0);
+            size = (size.0 * fit, size.1 * fit);
+        }
+        let size = PhysicalSize::new(
+            size.0.round().max(1.0) as u32,
+            size.1.round().max(1.0) as u32,
+        );
+        info!("window size {}x{}", size.width, size.height);
+        let _ = window.request_inner_size(size);
+    }
+
+    pub fn is_always_on_top(&self) -> bool {
+        self.always_on_top.load(Ordering::Relaxed)
+    }
+
+    /// Keeps the window above all others, or lets it be covered again.
+    pub fn set_always_on_top(&self, enabled: bool) {
+        let Some(window) = self.window.get() else {
+            return;
+        };
+        let level = if enabled {
+            WindowLevel::AlwaysOnTop
+        } else {
+            WindowLevel::Normal
+        };
+        window.set_window_level(level);
+        self.always_on_top.store(enabled, Ordering::Relaxed);
+    }
+}